@@ -0,0 +1,98 @@
+//! Animated sheet join: full re-joins of every frame vs a reused [`Compositor`].
+//!
+//! Joins 30 frames of a 48 capture, 8 column, grid of 480x270 captures
+//! with labels, progress bars & a header, with all or only some captures animated.
+//!
+//! `cargo bench --bench join`
+// dependencies are used by the lib
+#![allow(unused_crate_dependencies)]
+
+use rayon::prelude::*;
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+use vimg::command::{Compositor, Join};
+
+const CAPTURES: usize = 48;
+const FRAMES: u32 = 30;
+
+fn main() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join("vimg-bench-join");
+    write_captures(&dir)?;
+
+    for animated in [CAPTURES, 8] {
+        let full = time(|| {
+            (0..FRAMES)
+                .into_par_iter()
+                .try_for_each(|f| frame_join(&dir, "full", animated, f).run().map(|_| ()))
+        })?;
+
+        // compose consecutive frames on each thread, as vcs does
+        let chunk_len = (FRAMES as usize).div_ceil(rayon::current_num_threads());
+        let frames: Vec<_> = (0..FRAMES).collect();
+        let reused = time(|| {
+            frames.par_chunks(chunk_len).try_for_each(|frames| {
+                let mut compositor = Compositor::default();
+                for f in frames {
+                    compositor.compose(&frame_join(&dir, "reused", animated, *f))?;
+                }
+                Ok(())
+            })
+        })?;
+
+        for f in 0..FRAMES {
+            anyhow::ensure!(
+                fs::read(dir.join(format!("full-{f:02}.bmp")))?
+                    == fs::read(dir.join(format!("reused-{f:02}.bmp")))?,
+                "frame {f} differs"
+            );
+        }
+        println!(
+            "{animated}/{CAPTURES} captures animated: full re-joins {full:.2?}, compositor {reused:.2?}"
+        );
+    }
+
+    _ = fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+fn time(f: impl FnOnce() -> anyhow::Result<()>) -> anyhow::Result<Duration> {
+    let start = Instant::now();
+    f()?;
+    Ok(start.elapsed())
+}
+
+/// Join for frame `f` where the first `animated` captures change every frame.
+fn frame_join(dir: &Path, out: &str, animated: usize, f: u32) -> Join {
+    Join {
+        columns: 8,
+        output: dir.join(format!("{out}-{f:02}.bmp")),
+        label: (0..CAPTURES)
+            .map(|c| format!("{:02}:{:02}", c, c * 7 % 60))
+            .collect(),
+        progress: (0..CAPTURES).map(|c| c as f32 / CAPTURES as f32).collect(),
+        header: Some("bench.mkv  1/1  00:00 - 48:00".into()),
+        capture_images: (0..CAPTURES)
+            .map(|c| match c < animated {
+                true => dir.join(format!("c{c:02}-{f:02}.bmp")),
+                false => dir.join(format!("c{c:02}-00.bmp")),
+            })
+            .collect(),
+        ..<_>::default()
+    }
+}
+
+fn write_captures(dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    (0..CAPTURES).into_par_iter().try_for_each(|c| {
+        for f in 0..FRAMES {
+            let img = image::RgbImage::from_fn(480, 270, |x, y| {
+                image::Rgb([(x + f * 5) as u8, (y + c as u32 * 9) as u8, (x ^ y) as u8])
+            });
+            img.save(dir.join(format!("c{c:02}-{f:02}.bmp")))?;
+        }
+        Ok(())
+    })
+}
@@ -1,11 +1,20 @@
+mod encode;
 mod extract;
 mod join;
+mod metric;
+mod preview;
 mod print_completions;
+mod sprites;
+mod subtitle;
+mod thumb;
 mod vcs;
 
 pub use extract::*;
 pub use join::*;
+pub use preview::*;
 pub use print_completions::*;
+pub use sprites::*;
+pub use thumb::*;
 pub use vcs::*;
 
 use std::{fmt, str::FromStr};
@@ -77,6 +86,46 @@ impl fmt::Display for HumanDuration {
     }
 }
 
+/// Byte size, e.g. "2MB", "500KiB".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize {
+    pub bytes: u64,
+}
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(v: &str) -> Result<Self, Self::Err> {
+        let v = v.trim();
+        let unit_idx = v.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(v.len());
+        let (n, unit) = v.split_at(unit_idx);
+        let n: f64 = n.trim().parse()?;
+        let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1000_u64.pow(2),
+            "g" | "gb" => 1000_u64.pow(3),
+            "kib" => 1024,
+            "mib" => 1024_u64.pow(2),
+            "gib" => 1024_u64.pow(3),
+            unit => anyhow::bail!("invalid size unit {unit:?}"),
+        };
+        Ok(Self {
+            bytes: (n * multiplier as f64) as u64,
+        })
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bytes {
+            b if b >= 1000_u64.pow(2) => write!(f, "{:.2}MB", b as f64 / 1e6),
+            b if b >= 1000 => write!(f, "{:.1}KB", b as f64 / 1e3),
+            b => write!(f, "{b}B"),
+        }
+    }
+}
+
 pub fn sh_escape(path: &std::path::Path) -> std::borrow::Cow<'_, str> {
     shell_escape::escape(path.display().to_string().into())
 }
@@ -88,3 +137,92 @@ pub fn sh_escape_filename(path: &std::path::Path) -> std::borrow::Cow<'_, str> {
         .unwrap_or_default();
     shell_escape::escape(filename.into())
 }
+
+/// Spinner showing elapsed time & the current step, noting where temporary
+/// files are kept if `keep`.
+pub fn spinner(temp_dir: &std::path::Path, keep: bool) -> anyhow::Result<indicatif::ProgressBar> {
+    let spinner = indicatif::ProgressBar::new_spinner().with_style(
+        indicatif::ProgressStyle::default_spinner()
+            .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {msg}")?,
+    );
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+
+    if keep {
+        spinner.println(format!(
+            "Keeping temporary files in {}",
+            sh_escape(temp_dir)
+        ));
+    }
+    Ok(spinner)
+}
+
+/// Append a bicubic scale to `width` or `height`, preserving aspect, to `vfilter`.
+pub fn scale_vfilter(
+    vfilter: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> Option<String> {
+    let scale = match (width, height) {
+        (Some(w), _) => format!("scale={w}:-1:flags=bicubic"),
+        (None, Some(h)) => format!("scale=-1:{h}:flags=bicubic"),
+        (None, None) => return vfilter,
+    };
+    match vfilter {
+        Some(vf) => Some(format!("{vf},{scale}")),
+        None => Some(scale),
+    }
+}
+
+/// Hard link `from` to `to`, or copy if linking isn't possible e.g. across file systems.
+pub fn link_or_copy(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+    std::fs::hard_link(from, to).or_else(|_| std::fs::copy(from, to).map(|_| ()))
+}
+
+/// "dir/name.ext" -> "dir/name-N.ext"
+pub fn numbered(path: &std::path::Path, n: usize) -> std::path::PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{stem}-{n}.{}", ext.to_string_lossy())),
+        None => path.with_file_name(format!("{stem}-{n}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(v: &str) -> u64 {
+        v.parse::<ByteSize>().unwrap().bytes
+    }
+
+    #[test]
+    fn byte_size_units() {
+        assert_eq!(bytes("512"), 512);
+        assert_eq!(bytes("2MB"), 2_000_000);
+        assert_eq!(bytes(" 1.5 kb "), 1500);
+        assert_eq!(bytes("500KiB"), 512_000);
+        assert_eq!(bytes("1GiB"), 1 << 30);
+        assert!("2TB".parse::<ByteSize>().is_err());
+        assert!("MB".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn scale_vfilter_appends() {
+        assert_eq!(scale_vfilter(None, None, None), None);
+        assert_eq!(
+            scale_vfilter(None, Some(160), None).as_deref(),
+            Some("scale=160:-1:flags=bicubic")
+        );
+        assert_eq!(
+            scale_vfilter(Some("hflip".into()), None, Some(90)).as_deref(),
+            Some("hflip,scale=-1:90:flags=bicubic")
+        );
+    }
+
+    #[test]
+    fn byte_size_display() {
+        assert_eq!(ByteSize { bytes: 999 }.to_string(), "999B");
+        assert_eq!(ByteSize { bytes: 1500 }.to_string(), "1.5KB");
+        assert_eq!(ByteSize { bytes: 2_345_678 }.to_string(), "2.35MB");
+    }
+}
@@ -0,0 +1,604 @@
+//! Encoding image sequences into animated, or static, outputs with ffmpeg.
+use crate::{command::link_or_copy, process::CommandExt};
+use anyhow::{Context, ensure};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Maximum number of frames used in trial encodes.
+const TRIAL_FRAMES: usize = 8;
+
+/// Output format, determined by the output file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Avif,
+    Webp,
+    Mp4,
+    Webm,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("avif") => Ok(Self::Avif),
+            Some("webp") => Ok(Self::Webp),
+            Some("mp4") => Ok(Self::Mp4),
+            Some("webm") => Ok(Self::Webm),
+            _ => anyhow::bail!("unsupported output {path:?}, expected avif, webp, mp4 or webm"),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Avif => "avif",
+            Self::Webp => "webp",
+            Self::Mp4 => "mp4",
+            Self::Webm => "webm",
+        }
+    }
+
+    /// Whether the format stores a loop count.
+    pub fn supports_loop(self) -> bool {
+        matches!(self, Self::Avif | Self::Webp)
+    }
+
+    /// Default ffmpeg vcodec.
+    pub fn default_codec(self) -> &'static str {
+        match self {
+            Self::Avif => "libsvtav1",
+            Self::Webp => "libwebp",
+            Self::Mp4 => "libx264",
+            Self::Webm => "libvpx-vp9",
+        }
+    }
+}
+
+/// Supported ffmpeg encoders.
+const ENCODERS: &[Encoder] = &[
+    Encoder {
+        name: "libsvtav1",
+        formats: &[Format::Avif, Format::Webm],
+        preset: Param::direct("-preset", 0, 13, 6),
+        crf: Param::direct("-crf", 0, 63, 30),
+        pix_fmt: "yuv420p10le",
+        args: &[],
+    },
+    Encoder {
+        name: "libaom-av1",
+        formats: &[Format::Avif, Format::Webm],
+        preset: Param::direct("-cpu-used", 0, 8, 6),
+        crf: Param::direct("-crf", 0, 63, 30),
+        pix_fmt: "yuv420p10le",
+        args: &[],
+    },
+    Encoder {
+        name: "librav1e",
+        formats: &[Format::Avif, Format::Webm],
+        preset: Param::direct("-speed", 0, 10, 6),
+        crf: Param::direct("-qp", 0, 255, 100),
+        pix_fmt: "yuv420p10le",
+        args: &[],
+    },
+    Encoder {
+        name: "libx264",
+        formats: &[Format::Mp4],
+        preset: Param {
+            arg: "-preset",
+            min: 0,
+            max: 8,
+            default: 3,
+            map: Map::Names(&[
+                "veryslow",
+                "slower",
+                "slow",
+                "medium",
+                "fast",
+                "faster",
+                "veryfast",
+                "superfast",
+                "ultrafast",
+            ]),
+        },
+        crf: Param::direct("-crf", 0, 51, 23),
+        pix_fmt: "yuv420p",
+        args: &[],
+    },
+    Encoder {
+        name: "libvpx-vp9",
+        formats: &[Format::Webm],
+        preset: Param::direct("-cpu-used", 0, 5, 4),
+        crf: Param::direct("-crf", 0, 63, 32),
+        pix_fmt: "yuv420p",
+        args: &["-deadline", "good", "-row-mt", "1", "-b:v", "0"],
+    },
+    Encoder {
+        name: "libwebp",
+        formats: &[Format::Webp],
+        preset: Param::inverted("-compression_level", 6, 2),
+        crf: Param::inverted("-quality", 100, 25),
+        pix_fmt: "yuv420p",
+        args: &[],
+    },
+    Encoder {
+        name: "libwebp_anim",
+        formats: &[Format::Webp],
+        preset: Param::inverted("-compression_level", 6, 2),
+        crf: Param::inverted("-quality", 100, 25),
+        pix_fmt: "yuv420p",
+        args: &[],
+    },
+];
+
+/// Ffmpeg encoder & how to configure it.
+#[derive(Debug)]
+pub struct Encoder {
+    pub name: &'static str,
+    formats: &'static [Format],
+    /// Speed parameter, higher is faster.
+    preset: Param,
+    /// Quality parameter, lower is better quality.
+    crf: Param,
+    pix_fmt: &'static str,
+    /// Additional args always used.
+    args: &'static [&'static str],
+}
+
+/// Encoder numeric parameter.
+#[derive(Debug, Clone, Copy)]
+struct Param {
+    arg: &'static str,
+    /// Valid user values `min..=max`.
+    min: u8,
+    max: u8,
+    default: u8,
+    map: Map,
+}
+
+/// How user values map to ffmpeg values.
+#[derive(Debug, Clone, Copy)]
+enum Map {
+    Direct,
+    /// `max - value`, for parameters where higher means slower or better quality.
+    Inverted,
+    /// Index into named values.
+    Names(&'static [&'static str]),
+}
+
+impl Param {
+    const fn direct(arg: &'static str, min: u8, max: u8, default: u8) -> Self {
+        Self {
+            arg,
+            min,
+            max,
+            default,
+            map: Map::Direct,
+        }
+    }
+
+    const fn inverted(arg: &'static str, max: u8, default: u8) -> Self {
+        Self {
+            arg,
+            min: 0,
+            max,
+            default,
+            map: Map::Inverted,
+        }
+    }
+
+    fn ffmpeg_value(&self, value: u8) -> String {
+        match self.map {
+            Map::Direct => value.to_string(),
+            Map::Inverted => (self.max - value).to_string(),
+            Map::Names(names) => names[usize::from(value)].to_owned(),
+        }
+    }
+}
+
+/// Validated encoder settings.
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub format: Format,
+    pub encoder: &'static Encoder,
+    /// Quality level, lower is better quality.
+    pub crf: u8,
+    /// Speed preset, higher is faster.
+    pub preset: u8,
+    /// Number of times animated outputs play, 0 means loop forever.
+    /// Only used for formats that [`Format::supports_loop`].
+    pub loops: u32,
+}
+
+impl Settings {
+    /// Validate the codec, crf & preset for the output format and
+    /// check ffmpeg supports the encoder.
+    pub fn new(
+        format: Format,
+        codec: Option<&str>,
+        crf: Option<u8>,
+        preset: Option<u8>,
+    ) -> anyhow::Result<Self> {
+        let codec = codec.unwrap_or(format.default_codec());
+        let encoder = ENCODERS.iter().find(|e| e.name == codec).with_context(|| {
+            let names: Vec<_> = ENCODERS.iter().map(|e| e.name).collect();
+            format!(
+                "unsupported codec {codec}, expected one of: {}",
+                names.join(", ")
+            )
+        })?;
+        ensure!(
+            encoder.formats.contains(&format),
+            "codec {codec} does not support {format:?} output"
+        );
+
+        let crf = crf.unwrap_or(encoder.crf.default);
+        let preset = preset.unwrap_or(encoder.preset.default);
+        for (name, value, param) in [
+            ("crf", crf, encoder.crf),
+            ("preset", preset, encoder.preset),
+        ] {
+            ensure!(
+                (param.min..=param.max).contains(&value),
+                "invalid {name} {value} for {codec}, expected {}-{}",
+                param.min,
+                param.max,
+            );
+        }
+
+        ensure_ffmpeg_encoder(codec)?;
+
+        Ok(Self {
+            format,
+            encoder,
+            crf,
+            preset,
+            loops: 0,
+        })
+    }
+}
+
+/// Check `ffmpeg -encoders` includes `codec`.
+fn ensure_ffmpeg_encoder(codec: &str) -> anyhow::Result<()> {
+    let out = Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .arg("-encoders")
+        .output()
+        .context("failed to run ffmpeg, is it installed & in $PATH?")?;
+    let encoders = String::from_utf8_lossy(&out.stdout);
+    // e.g. " V....D libsvtav1            SVT-AV1(Scalable Video Technology for AV1) encoder"
+    let available = encoders
+        .lines()
+        .any(|l| l.split_whitespace().nth(1) == Some(codec));
+    ensure!(
+        available,
+        "ffmpeg encoder {codec} is not available, see `ffmpeg -encoders`"
+    );
+    Ok(())
+}
+
+/// Ffmpeg encode of an image sequence.
+#[derive(Debug, Clone)]
+pub struct Encode<'a> {
+    pub input: Input<'a>,
+    pub settings: &'a Settings,
+    pub output: &'a Path,
+}
+
+/// Encode input frames.
+#[derive(Debug, Clone, Copy)]
+pub enum Input<'a> {
+    /// Ffmpeg image2 input pattern, e.g. "dir/name-%02d.bmp", played at a constant fps.
+    Sequence { pattern: &'a Path, fps: f32 },
+    /// Ffconcat list of frames each with their own duration, see [`write_concat`].
+    Concat(&'a Path),
+}
+
+/// Write an ffconcat list of `(frame, duration_s)` for variable frame timing.
+///
+/// Frames in the same directory as the list are written as bare file names,
+/// as the concat demuxer resolves relative paths against the list's directory.
+pub fn write_concat(list: &Path, frames: &[(PathBuf, f32)]) -> anyhow::Result<()> {
+    let file_line = |path: &Path| -> anyhow::Result<String> {
+        let path = match path.parent() == list.parent() {
+            true => PathBuf::from(path.file_name().context("invalid frame")?),
+            false => fs::canonicalize(path).with_context(|| format!("{path:?}"))?,
+        };
+        let path = path.to_string_lossy().replace('\'', r"'\''");
+        Ok(format!("file '{path}'\n"))
+    };
+    let mut out = String::from("ffconcat version 1.0\n");
+    for (frame, duration_s) in frames {
+        out += &file_line(frame)?;
+        out += &format!("duration {duration_s}\n");
+    }
+    // the last duration is only respected when followed by another file
+    if let Some((last, _)) = frames.last() {
+        out += &file_line(last)?;
+    }
+    fs::write(list, out)?;
+    Ok(())
+}
+
+impl Encode<'_> {
+    pub fn run(&self) -> anyhow::Result<()> {
+        let Self {
+            input,
+            settings,
+            output,
+        } = self;
+        let Settings {
+            format,
+            encoder,
+            crf,
+            preset,
+            loops,
+        } = settings;
+
+        let mut cmd = Command::new("ffmpeg");
+        match input {
+            Input::Sequence { pattern, fps } => cmd.arg2("-r", fps).arg2("-i", *pattern),
+            Input::Concat(list) => cmd
+                .arg2("-f", "concat")
+                .arg2("-safe", 0)
+                .arg2("-i", *list)
+                // keep frame durations instead of duplicating frames to a constant rate
+                .arg2("-fps_mode", "vfr"),
+        };
+        cmd.arg2("-c:v", encoder.name)
+            .arg2(encoder.preset.arg, encoder.preset.ffmpeg_value(*preset))
+            .arg2(encoder.crf.arg, encoder.crf.ffmpeg_value(*crf))
+            .arg2("-pix_fmt", encoder.pix_fmt)
+            .args(encoder.args);
+
+        if matches!(format, Format::Mp4 | Format::Webm) {
+            // yuv420p requires even dimensions
+            cmd.arg2("-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2")
+                // loops always seek back to the start so intermediate keyframes aren't needed
+                .arg2("-g", 9999)
+                .arg("-an");
+        }
+        if *format == Format::Mp4 {
+            cmd.arg2("-movflags", "+faststart");
+        }
+        // avif muxer defaults to infinite & older ffmpeg versions lack the option
+        if *format == Format::Webp || (*format == Format::Avif && *loops != 0) {
+            cmd.arg2("-loop", loops);
+        }
+
+        let out = cmd.arg("-y").arg(*output).output()?;
+        ensure!(
+            out.status.success(),
+            "ffmpeg encode failed\n---stderr---\n{}\n------",
+            String::from_utf8_lossy(&out.stderr).trim(),
+        );
+        Ok(())
+    }
+}
+
+impl Settings {
+    /// Search for the best quality (lowest) crf where encoding `frames`, each
+    /// `(frame, duration_s)`, is estimated to result in an output no larger than `max_bytes`.
+    ///
+    /// Uses trial encodes of a subset of the frames written into `work_dir`,
+    /// as an image sequence or, if `variable` frame timing, an ffconcat list
+    /// matching the final encode.
+    pub fn max_size_crf(
+        &self,
+        frames: &[(PathBuf, f32)],
+        variable: bool,
+        max_bytes: u64,
+        work_dir: &Path,
+    ) -> anyhow::Result<u8> {
+        ensure!(!frames.is_empty(), "no frames to encode");
+        fs::create_dir_all(work_dir)?;
+
+        // evenly spaced subset of frames, keeping the same playback duration
+        let step = frames.len().div_ceil(TRIAL_FRAMES);
+        let trial_frames: Vec<_> = frames
+            .chunks(step)
+            .map(|c| (c[0].0.clone(), c.iter().map(|(_, d)| d).sum::<f32>()))
+            .collect();
+        let ext = frames[0]
+            .0
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let pattern = work_dir.join(format!("trial-%03d.{ext}"));
+        let concat_list = work_dir.join("trial.ffconcat");
+        let input = match variable {
+            true => {
+                write_concat(&concat_list, &trial_frames)?;
+                Input::Concat(&concat_list)
+            }
+            false => {
+                for (n, (frame, _)) in trial_frames.iter().enumerate() {
+                    let link = work_dir.join(format!("trial-{n:03}.{ext}"));
+                    _ = fs::remove_file(&link);
+                    link_or_copy(frame, &link)?;
+                }
+                Input::Sequence {
+                    pattern: &pattern,
+                    fps: 1.0 / trial_frames[0].1,
+                }
+            }
+        };
+        let trial_out = work_dir.join(format!("trial.{}", self.format.extension()));
+
+        let (mut lo, mut hi) = (self.encoder.crf.min, self.encoder.crf.max);
+        while lo < hi {
+            let crf = lo + (hi - lo) / 2;
+            Encode {
+                input,
+                settings: &Self { crf, ..*self },
+                output: &trial_out,
+            }
+            .run()?;
+            let estimate =
+                fs::metadata(&trial_out)?.len() * frames.len() as u64 / trial_frames.len() as u64;
+            match estimate <= max_bytes {
+                true => hi = crf,
+                false => lo = crf + 1,
+            }
+        }
+        Ok(lo)
+    }
+
+    /// Search for the lowest crf where `encode(crf)`, returning the output bytes,
+    /// is no larger than `max_bytes`, starting from the estimated `self.crf`.
+    ///
+    /// Stops early once an output is within 10% of `max_bytes`. Returns the best
+    /// fitting crf & its bytes, or `None` if even the maximum crf is too large.
+    pub fn fit_crf(
+        &self,
+        max_bytes: u64,
+        mut encode: impl FnMut(u8) -> anyhow::Result<u64>,
+    ) -> anyhow::Result<Option<(u8, u64)>> {
+        let (mut lo, mut hi) = (self.encoder.crf.min, self.encoder.crf.max);
+        let mut crf = self.crf.clamp(lo, hi);
+        let mut best = None;
+        loop {
+            let bytes = encode(crf)?;
+            if bytes <= max_bytes {
+                best = Some((crf, bytes));
+                hi = crf;
+                if bytes >= max_bytes / 10 * 9 {
+                    return Ok(best);
+                }
+            } else {
+                lo = crf + 1;
+            }
+            match best {
+                Some((best_crf, _)) if lo >= best_crf => return Ok(best),
+                None if lo > hi => return Ok(None),
+                _ => crf = lo + (hi - lo) / 2,
+            }
+        }
+    }
+
+    /// Maximum crf value.
+    pub fn max_crf(&self) -> u8 {
+        self.encoder.crf.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concat_list_paths() {
+        let dir = std::env::temp_dir().join(format!("vimg-concat-{}", std::process::id()));
+        let other = dir.join("other");
+        fs::create_dir_all(&other).unwrap();
+        let elsewhere = other.join("it's.bmp");
+        fs::write(&elsewhere, b"").unwrap();
+
+        let list = dir.join("play.ffconcat");
+        let frames = [
+            (dir.join("a-1.bmp"), 0.05),
+            (dir.join("it's-2.bmp"), 0.1),
+            (elsewhere.clone(), 0.05),
+        ];
+        write_concat(&list, &frames).unwrap();
+        let written = fs::read_to_string(&list).unwrap();
+        let elsewhere = fs::canonicalize(&elsewhere).unwrap();
+        _ = fs::remove_dir_all(&dir);
+        assert_eq!(
+            written,
+            format!(
+                "ffconcat version 1.0\n\
+                 file 'a-1.bmp'\nduration 0.05\n\
+                 file 'it'\\''s-2.bmp'\nduration 0.1\n\
+                 file '{0}'\nduration 0.05\n\
+                 file '{0}'\n",
+                elsewhere.display().to_string().replace('\'', r"'\''")
+            )
+        );
+    }
+
+    fn fit_crf(estimate: u8, size: impl Fn(u8) -> u64, max_bytes: u64) -> (Option<u8>, Vec<u8>) {
+        let settings = Settings {
+            format: Format::Avif,
+            encoder: &ENCODERS[0],
+            crf: estimate,
+            preset: 0,
+            loops: 0,
+        };
+        let mut tried = vec![];
+        let best = settings
+            .fit_crf(max_bytes, |crf| {
+                tried.push(crf);
+                Ok(size(crf))
+            })
+            .unwrap();
+        (best.map(|(crf, _)| crf), tried)
+    }
+
+    #[test]
+    fn fit_crf_lowers_pessimistic_estimate() {
+        // 1000 bytes at crf 0, halving every 10 crf
+        let size = |crf: u8| 1000 >> (crf / 10);
+        let (best, tried) = fit_crf(50, size, 300);
+        assert_eq!(best, Some(20));
+        assert_eq!(tried.first(), Some(&50));
+        assert!(tried.iter().all(|crf| size(*crf) > 300 || *crf >= 20));
+    }
+
+    #[test]
+    fn fit_crf_raises_optimistic_estimate() {
+        let size = |crf: u8| 1000 >> (crf / 10);
+        assert_eq!(fit_crf(5, size, 300).0, Some(20));
+        // nothing fits
+        assert_eq!(fit_crf(5, size, 10).0, None);
+    }
+
+    #[test]
+    fn fit_crf_close_enough() {
+        let size = |crf: u8| 1000 - crf as u64 * 10;
+        let (best, tried) = fit_crf(10, size, 950);
+        assert_eq!(best, Some(10));
+        assert_eq!(tried, [10]);
+    }
+
+    fn encoder(name: &str) -> &'static Encoder {
+        ENCODERS.iter().find(|e| e.name == name).unwrap()
+    }
+
+    #[test]
+    fn x264_preset_names() {
+        let preset = encoder("libx264").preset;
+        assert_eq!(preset.ffmpeg_value(0), "veryslow");
+        assert_eq!(preset.ffmpeg_value(3), "medium");
+        assert_eq!(preset.ffmpeg_value(preset.max), "ultrafast");
+    }
+
+    #[test]
+    fn webp_inverted_params() {
+        let webp = encoder("libwebp");
+        // lower crf is better quality
+        assert_eq!(webp.crf.ffmpeg_value(0), "100");
+        assert_eq!(webp.crf.ffmpeg_value(25), "75");
+        // higher preset is faster
+        assert_eq!(webp.preset.ffmpeg_value(6), "0");
+        assert_eq!(webp.preset.ffmpeg_value(2), "4");
+    }
+
+    #[test]
+    fn settings_out_of_range() {
+        let err = Settings::new(Format::Mp4, None, Some(52), None).unwrap_err();
+        assert_eq!(err.to_string(), "invalid crf 52 for libx264, expected 0-51");
+        let err = Settings::new(Format::Avif, None, None, Some(14)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid preset 14 for libsvtav1, expected 0-13"
+        );
+        let err = Settings::new(Format::Webp, Some("libwebp"), Some(101), None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid crf 101 for libwebp, expected 0-100"
+        );
+        let err = Settings::new(Format::Mp4, Some("libwebp"), None, None).unwrap_err();
+        assert_eq!(err.to_string(), "codec libwebp does not support Mp4 output");
+    }
+}
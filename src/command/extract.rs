@@ -1,22 +1,46 @@
 use crate::{
-    command::{DurationOrPercent, HumanDuration, sh_escape},
+    command::{DurationOrPercent, HumanDuration, link_or_copy, sh_escape},
     process::CommandExt,
 };
-use anyhow::{Context, ensure};
+use anyhow::{Context, bail, ensure};
 use rayon::prelude::*;
 use std::{
+    collections::HashMap,
     fmt, fs,
+    ops::RangeInclusive,
     path::{Path, PathBuf},
     process::Command,
+    sync::Mutex,
+    time::Duration,
 };
 
+/// Seconds decoded before the capture point when seeking accurately.
+const ACCURATE_SEEK_PREROLL_S: f32 = 10.0;
+
+/// Default `--capture-timeout`.
+pub const CAPTURE_TIMEOUT: HumanDuration = HumanDuration { seconds: 120.0 };
+
+/// Default `--capture-retries`.
+pub const CAPTURE_RETRIES: u32 = 2;
+
+/// Seconds a capture start is moved by for each retry.
+const RETRY_NUDGE_S: f32 = 1.0;
+
 /// Generate capture bmp images from a video using ffmpeg.
 #[derive(clap::Parser, Debug, Clone)]
 #[group(skip)]
 pub struct Extract {
     /// Number of equidistant points in the video to capture.
-    #[arg(long, short)]
-    pub number: u32,
+    ///
+    /// Use this or --interval (not both).
+    #[arg(long, short, required_unless_present = "interval")]
+    pub number: Option<u32>,
+
+    /// Capture a point every interval, e.g. "10s", instead of a fixed number of points.
+    ///
+    /// Use this or -n (not both).
+    #[arg(long, short = 'i', conflicts_with = "number")]
+    pub interval: Option<HumanDuration>,
 
     /// Time or percentage at the start to ignore when calculating capture points.
     #[arg(long = "ignore-start", default_value = "0s")]
@@ -40,6 +64,23 @@ pub struct Extract {
     #[arg(long)]
     pub vfilter: Option<String>,
 
+    /// How to handle captures where ffmpeg fails to extract all frames.
+    #[arg(long, value_enum, default_value_t = MissingFrames::Duplicate)]
+    pub missing_frames: MissingFrames,
+
+    /// Maximum duration of each ffmpeg capture, after which it is killed & retried.
+    #[arg(long, default_value_t = CAPTURE_TIMEOUT)]
+    pub capture_timeout: HumanDuration,
+
+    /// Number of times a failed, or timed out, capture is retried with a slightly moved start.
+    #[arg(long, default_value_t = CAPTURE_RETRIES)]
+    pub capture_retries: u32,
+
+    /// Skip captures that still fail after retries, instead of failing the whole run.
+    /// Vcs draws a placeholder cell showing the error.
+    #[arg(long, default_value_t = false)]
+    pub skip_failed: bool,
+
     /// Number of threads / concurrent ffmpeg calls. 0=auto.
     #[arg(long, short = 'T', default_value_t = 3)]
     pub threads: usize,
@@ -55,8 +96,13 @@ pub struct Extract {
 
 impl Extract {
     pub fn run(&self) -> anyhow::Result<ExtractData> {
+        self.run_frames(|_| self.capture_frames())
+    }
+
+    /// Extract using `frames(n)` frames for the nth capture, rather than
+    /// always `capture_frames`. Fewer frames are spread over the same capture time.
+    pub fn run_frames(&self, frames: impl Fn(usize) -> u32 + Sync) -> anyhow::Result<ExtractData> {
         let Self {
-            number,
             ignore_start,
             ignore_end,
             threads,
@@ -65,12 +111,7 @@ impl Extract {
             ..
         } = self;
 
-        let video_duration_s = ffprobe::ffprobe(video)?
-            .format
-            .duration
-            .context("invalid video duration")?
-            .parse::<f32>()
-            .context("invalid video duration")?;
+        let video_duration_s = video_duration(video)?;
 
         let duration_s = video_duration_s
             - ignore_start.to_secs(video_duration_s)
@@ -81,6 +122,10 @@ impl Extract {
             "invalid negative video duration minus offsets"
         );
 
+        let starts = self.capture_starts(video_duration_s)?;
+        // range capture points may be shifted within
+        let shift_bounds = self.capture_bounds(video_duration_s);
+
         let out_dir = match output_dir {
             Some(dir) => {
                 fs::create_dir_all(dir)?;
@@ -93,41 +138,160 @@ impl Extract {
             .num_threads(*threads)
             .build()?
             .install(|| {
-                let out_templates = (0..*number)
-                    .into_par_iter()
-                    .map(|n| {
-                        let interval = duration_s / *number as f32;
-                        let start_s = ignore_start.to_secs(video_duration_s)
-                            + interval * 0.5
-                            + interval * n as f32;
-                        let start_s = start_s.min(video_duration_s - self.capture_time.seconds);
-                        let out_template = self.out_template(start_s, duration_s);
-                        self.capture(start_s, &out_template)?;
-                        Ok(out_template)
+                let claims = Claims::new(starts.iter().map(|s| *s as u32));
+
+                let captures = starts
+                    .par_iter()
+                    .enumerate()
+                    .map(|(n, &first_start_s)| {
+                        let frames = frames(n);
+                        let first = self.out_template(first_start_s, duration_s, frames);
+
+                        // retry alternately later & earlier, moving further each time
+                        let mut last_err = None;
+                        for attempt in 0..=self.capture_retries {
+                            let nudge = RETRY_NUDGE_S * attempt.div_ceil(2) as f32;
+                            let nudge = if attempt % 2 == 1 { nudge } else { -nudge };
+                            let start_s = match attempt {
+                                0 => first_start_s,
+                                _ => (first_start_s + nudge)
+                                    .clamp(*shift_bounds.start(), *shift_bounds.end()),
+                            };
+                            let out_template = self.out_template(start_s, duration_s, frames);
+                            // don't overwrite other captures' files
+                            if attempt != 0 && !claims.claim(out_template.seconds) {
+                                continue;
+                            }
+                            match self.capture(start_s, &out_template, false) {
+                                Ok(()) => {
+                                    if attempt != 0 {
+                                        claims.release(first.seconds);
+                                    }
+                                    return Ok(Ok((start_s, out_template)));
+                                }
+                                Err(err) => {
+                                    // the first is kept claimed for a --skip-failed placeholder
+                                    let owned = match attempt {
+                                        0 => claims.is_sole(first.seconds),
+                                        _ => claims.release(out_template.seconds),
+                                    };
+                                    if owned {
+                                        remove_frames(&out_template, &out_dir);
+                                    }
+                                    last_err = Some(err);
+                                }
+                            }
+                        }
+                        let err = last_err.context("capture not attempted")?;
+                        match self.skip_failed {
+                            true => Ok(Err((first, err))),
+                            false => Err(err),
+                        }
                     })
                     .collect::<anyhow::Result<Vec<_>>>()?;
 
-                let warnings = self.fix_missing(&out_templates, &out_dir)?;
+                let (out_templates, mut warnings) = self.fix_missing(
+                    captures
+                        .iter()
+                        .filter_map(|c| c.as_ref().ok().cloned())
+                        .collect(),
+                    &shift_bounds,
+                    &claims,
+                    &out_dir,
+                )?;
+
+                // keep failed templates in position
+                let mut fixed = out_templates.into_iter();
+                let mut out_templates = Vec::with_capacity(captures.len());
+                let mut failed = Vec::new();
+                for capture in captures {
+                    match capture {
+                        Ok(_) => out_templates.extend(fixed.next()),
+                        Err((tmpl, err)) => {
+                            // first line, without ffmpeg's stderr
+                            let err = format!("{err:#}")
+                                .lines()
+                                .next()
+                                .unwrap_or_default()
+                                .to_owned();
+                            warnings.push(format!("Skipped failed capture {tmpl}: {err}"));
+                            out_templates.push(tmpl.clone());
+                            failed.push((tmpl, err));
+                        }
+                    }
+                }
 
                 Ok(ExtractData {
                     out_templates,
+                    video_duration_s,
                     warnings,
+                    failed,
                 })
             })
     }
 
+    /// Start seconds of each capture, in the middle of equal intervals of the
+    /// duration minus ignored regions, clamped so captures end within it.
+    pub fn capture_starts(&self, video_duration_s: f32) -> anyhow::Result<Vec<f32>> {
+        let bounds = self.capture_bounds(video_duration_s);
+        let duration_s = video_duration_s
+            - self.ignore_start.to_secs(video_duration_s)
+            - self.ignore_end.to_secs(video_duration_s);
+
+        let (number, interval) = match (self.number, self.interval) {
+            (_, Some(interval)) => {
+                ensure!(interval.seconds > 0.0, "invalid interval must be non-zero");
+                // a partial last interval gets a capture too, clamped within bounds
+                let number = ((duration_s / interval.seconds).ceil() as u32).max(1);
+                (number, interval.seconds)
+            }
+            (Some(number), None) => (number, duration_s / number as f32),
+            (None, None) => anyhow::bail!("one of --number or --interval is required"),
+        };
+
+        Ok((0..number)
+            .map(|n| {
+                let start_s = bounds.start() + interval * 0.5 + interval * n as f32;
+                start_s.min(*bounds.end())
+            })
+            .collect())
+    }
+
+    /// Range of capture start seconds outside the ignored regions, leaving
+    /// time for the capture.
+    fn capture_bounds(&self, video_duration_s: f32) -> RangeInclusive<f32> {
+        let start = self.ignore_start.to_secs(video_duration_s);
+        let end = video_duration_s - self.ignore_end.to_secs(video_duration_s);
+        start..=(end - self.capture_time.seconds).max(start)
+    }
+
     pub fn capture_frames(&self) -> u32 {
         self.capture_frames.unwrap_or(1)
     }
 
-    fn out_template(&self, start_s: f32, duration_s: f32) -> OutTemplate {
+    fn out_template(&self, start_s: f32, duration_s: f32, frames: u32) -> OutTemplate {
         let prefix = self.video.with_extension("");
         let prefix = prefix.file_name().unwrap_or_default().to_string_lossy();
 
-        OutTemplate::new(prefix, start_s as _, duration_s as _, self.capture_frames())
+        OutTemplate::new(
+            prefix,
+            start_s as _,
+            duration_s as _,
+            self.capture_frames(),
+            frames,
+        )
     }
 
-    fn capture(&self, start_s: f32, out_template: &OutTemplate) -> anyhow::Result<()> {
+    /// Capture frames starting at `start_s`.
+    ///
+    /// `accurate` seeks by decoding from a point before `start_s`, which is slower
+    /// but avoids frames lost to inaccurate keyframe seeking.
+    fn capture(
+        &self,
+        start_s: f32,
+        out_template: &OutTemplate,
+        accurate: bool,
+    ) -> anyhow::Result<()> {
         let Self {
             capture_time,
             vfilter,
@@ -135,7 +299,7 @@ impl Extract {
             video,
             ..
         } = self;
-        let capture_frames = self.capture_frames();
+        let capture_frames = out_template.frames;
         ensure!(
             capture_frames > 0,
             "invalid capture-frames must be non-zero"
@@ -151,17 +315,24 @@ impl Extract {
         };
         out.push(out_template.to_string());
 
+        let preroll_s = match accurate {
+            true => start_s.min(ACCURATE_SEEK_PREROLL_S),
+            false => 0.0,
+        };
+
         let out = Command::new("ffmpeg")
-            .arg2("-ss", start_s)
-            .arg2("-t", capture_time.seconds)
+            .arg2("-ss", start_s - preroll_s)
+            .arg2("-t", capture_time.seconds + preroll_s)
             .arg2("-i", video)
+            .arg2_if(accurate, "-ss", preroll_s)
             .arg2("-r", format!("{capture_frames}/{}", capture_time.seconds))
             .arg2("-fps_mode", "cfr")
             .arg2_opt("-vf", vfilter.as_ref())
             .arg2("-vframes", capture_frames)
             .arg("-y")
             .arg(&out)
-            .output()?;
+            .output_timeout(Duration::from_secs_f32(self.capture_timeout.seconds))
+            .context("ffmpeg capture failed")?;
 
         ensure!(
             out.status.success(),
@@ -172,49 +343,240 @@ impl Extract {
         Ok(())
     }
 
-    /// Check extractions and fix missing. Returns a list of warnings.
+    /// Check extractions and handle missing frames according to `--missing-frames`.
+    /// Returns the, possibly shifted, templates & a list of warnings.
     ///
     /// In fairly rare cases ffmpeg can fail to extract the expected number of frames.
-    /// Auto fixing will simply cover these missing frames with duplicates of the previous frame.
+    /// Frames still missing after any retry or shift are covered with duplicates
+    /// of the previous frame.
     fn fix_missing(
         &self,
-        extracts: &[OutTemplate],
+        captures: Vec<(f32, OutTemplate)>,
+        shift_bounds: &RangeInclusive<f32>,
+        claims: &Claims,
         temp_dir: &Path,
-    ) -> anyhow::Result<Vec<String>> {
-        let mut warnings = Vec::new();
-
-        // ensure all captures exist
-        for tmpl in extracts {
-            let mut first = temp_dir.to_path_buf();
-            first.push(tmpl.with_frame(1));
-            ensure!(first.is_file(), "Failed to extract: {}", sh_escape(&first));
-
-            let mut prev = first;
-            let mut fixes = 0;
-            for f in 2..=self.capture_frames() {
-                let mut next = temp_dir.to_path_buf();
-                next.push(tmpl.with_frame(f));
-                if !next.is_file() {
-                    fs::hard_link(&prev, &next).or_else(|_| fs::copy(&prev, &next).map(|_| ()))?;
-                    fixes += 1;
+    ) -> anyhow::Result<(Vec<OutTemplate>, Vec<String>)> {
+        let fixed = captures
+            .into_par_iter()
+            .map(|(start_s, mut tmpl)| {
+                let mut warnings = Vec::new();
+
+                let missing = missing_frames(&tmpl, temp_dir);
+                if missing != 0 {
+                    match self.missing_frames {
+                        MissingFrames::Duplicate => {}
+                        MissingFrames::Error => bail!(
+                            "Failed to extract {missing} of {} {tmpl} frames",
+                            tmpl.frames
+                        ),
+                        MissingFrames::Retry => {
+                            // re-capture separately so a failure keeps the original frames
+                            let retry = OutTemplate {
+                                prefix: format!("{}-retry", tmpl.prefix),
+                                ..tmpl.clone()
+                            };
+                            match self.capture(start_s, &retry, true) {
+                                Ok(()) => {
+                                    for f in 1..=tmpl.frames {
+                                        let frame = temp_dir.join(retry.with_frame(f));
+                                        if frame.is_file() {
+                                            fs::rename(frame, temp_dir.join(tmpl.with_frame(f)))?;
+                                        }
+                                    }
+                                    // duplicate any still missing
+                                    let still_missing = missing_frames(&tmpl, temp_dir);
+                                    warnings.push(match still_missing {
+                                        0 => format!(
+                                            "Re-captured {tmpl} with accurate seeking to cover \
+                                             {missing} missing frames"
+                                        ),
+                                        n => format!(
+                                            "Re-captured {tmpl} with accurate seeking, {n} of \
+                                             {missing} frames still missing"
+                                        ),
+                                    });
+                                }
+                                Err(err) => {
+                                    remove_frames(&retry, temp_dir);
+                                    warnings.push(format!(
+                                        "Failed to re-capture {tmpl} with accurate seeking, \
+                                         duplicating {missing} missing frames: {err}"
+                                    ));
+                                }
+                            }
+                        }
+                        MissingFrames::Shift => {
+                            if let Some(shifted) =
+                                self.shift(start_s, &tmpl, shift_bounds, claims, temp_dir)?
+                            {
+                                warnings.push(format!(
+                                    "Shifted {tmpl} to {shifted} to avoid {missing} missing frames"
+                                ));
+                                tmpl = shifted;
+                            }
+                        }
+                    }
+                }
+
+                let mut first = temp_dir.to_path_buf();
+                first.push(tmpl.with_frame(1));
+                ensure!(first.is_file(), "Failed to extract: {}", sh_escape(&first));
+
+                let mut prev = first;
+                let mut fixes = 0;
+                for f in 2..=tmpl.frames {
+                    let mut next = temp_dir.to_path_buf();
+                    next.push(tmpl.with_frame(f));
+                    if !next.is_file() {
+                        link_or_copy(&prev, &next)?;
+                        fixes += 1;
+                    }
+                    prev = next;
+                }
+                if fixes != 0 {
+                    warnings.push(format!(
+                        "Duplicated {fixes} captures to cover missing {tmpl} frames"
+                    ));
                 }
-                prev = next;
+                Ok((tmpl, warnings))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let warnings = fixed.iter().flat_map(|(_, w)| w.clone()).collect();
+        Ok((fixed.into_iter().map(|(tmpl, _)| tmpl).collect(), warnings))
+    }
+
+    /// Capture at points increasingly far, earlier & later, from `start_s` returning
+    /// the first capture with all frames.
+    ///
+    /// Points with the same file names as other captures are skipped. Frames of
+    /// unsuccessful attempts, & the original if shifted, are removed.
+    fn shift(
+        &self,
+        start_s: f32,
+        tmpl: &OutTemplate,
+        bounds: &RangeInclusive<f32>,
+        claims: &Claims,
+        temp_dir: &Path,
+    ) -> anyhow::Result<Option<OutTemplate>> {
+        let step = self.capture_time.seconds;
+        for offset in [-step, step, -2.0 * step, 2.0 * step] {
+            let shifted_s = (start_s + offset).clamp(*bounds.start(), *bounds.end());
+            let shifted = OutTemplate {
+                seconds: shifted_s as _,
+                ..tmpl.clone()
+            };
+            if !claims.claim(shifted.seconds) {
+                continue;
             }
-            if fixes != 0 {
-                warnings.push(format!(
-                    "Duplicated {fixes} captures to cover missing {tmpl} frames"
-                ));
+            if self.capture(shifted_s, &shifted, false).is_ok()
+                && missing_frames(&shifted, temp_dir) == 0
+            {
+                if claims.release(tmpl.seconds) {
+                    remove_frames(tmpl, temp_dir);
+                }
+                return Ok(Some(shifted));
             }
+            claims.release(shifted.seconds);
+            remove_frames(&shifted, temp_dir);
         }
+        Ok(None)
+    }
+}
 
-        Ok(warnings)
+/// How captures with frames ffmpeg failed to extract are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MissingFrames {
+    /// Cover missing frames with duplicates of the previous frame.
+    #[default]
+    Duplicate,
+    /// Re-run the capture with slower, accurate seeking.
+    Retry,
+    /// Move the capture point earlier or later in the video.
+    Shift,
+    /// Fail.
+    Error,
+}
+
+/// Capture seconds in use, as capture file names only differ by seconds.
+/// Used to avoid moved captures overwriting others' frames.
+struct Claims(Mutex<HashMap<u32, usize>>);
+
+impl Claims {
+    fn new(seconds: impl IntoIterator<Item = u32>) -> Self {
+        let mut claims = HashMap::new();
+        for s in seconds {
+            *claims.entry(s).or_default() += 1;
+        }
+        Self(Mutex::new(claims))
     }
+
+    /// Claim unused `seconds`. Returns `false` if already in use.
+    fn claim(&self, seconds: u32) -> bool {
+        let mut claims = self.0.lock().unwrap();
+        match claims.contains_key(&seconds) {
+            true => false,
+            false => {
+                claims.insert(seconds, 1);
+                true
+            }
+        }
+    }
+
+    /// Whether `seconds` is used by a single capture.
+    fn is_sole(&self, seconds: u32) -> bool {
+        self.0.lock().unwrap().get(&seconds) == Some(&1)
+    }
+
+    /// Release a claim of `seconds`. Returns `true` if no other capture uses it,
+    /// so its files may be removed.
+    fn release(&self, seconds: u32) -> bool {
+        let mut claims = self.0.lock().unwrap();
+        match claims.get_mut(&seconds) {
+            Some(n) if *n > 1 => {
+                *n -= 1;
+                false
+            }
+            _ => {
+                claims.remove(&seconds);
+                true
+            }
+        }
+    }
+}
+
+/// Number of frames of `tmpl` not in `dir`.
+fn missing_frames(tmpl: &OutTemplate, dir: &Path) -> usize {
+    (1..=tmpl.frames)
+        .filter(|f| !dir.join(tmpl.with_frame(*f)).is_file())
+        .count()
+}
+
+fn remove_frames(tmpl: &OutTemplate, dir: &Path) {
+    for f in 1..=tmpl.frames {
+        _ = fs::remove_file(dir.join(tmpl.with_frame(f)));
+    }
+}
+
+/// Probe the video duration in seconds.
+pub fn video_duration(video: &Path) -> anyhow::Result<f32> {
+    ffprobe::ffprobe(video)?
+        .format
+        .duration
+        .context("invalid video duration")?
+        .parse::<f32>()
+        .context("invalid video duration")
 }
 
 pub struct ExtractData {
     /// All ffmpeg capture output templates.
     pub out_templates: Vec<OutTemplate>,
+    /// Full duration of the input video in seconds.
+    pub video_duration_s: f32,
     pub warnings: Vec<String>,
+    /// Captures skipped with `--skip-failed` & their error. These are included
+    /// in `out_templates` but have no frames.
+    pub failed: Vec<(OutTemplate, String)>,
 }
 
 /// "prefix-Ss-F.bmp" template.
@@ -225,12 +587,20 @@ pub struct ExtractData {
 pub struct OutTemplate {
     pub prefix: String,
     pub seconds: u32,
+    /// Number of frames captured.
+    pub frames: u32,
     second_w: usize,
     frame_w: usize,
 }
 
 impl OutTemplate {
-    fn new(prefix: impl Into<String>, seconds: u32, max_seconds: u32, max_frames: u32) -> Self {
+    fn new(
+        prefix: impl Into<String>,
+        seconds: u32,
+        max_seconds: u32,
+        max_frames: u32,
+        frames: u32,
+    ) -> Self {
         let second_w = max_seconds.to_string().len();
         let frame_w = max_frames.to_string().len();
         let mut prefix = prefix.into();
@@ -241,11 +611,21 @@ impl OutTemplate {
         Self {
             prefix,
             seconds,
+            frames,
             second_w,
             frame_w,
         }
     }
 
+    /// Return the frame number of this capture showing the same moment as frame `f`
+    /// of a capture with `max_frames` frames over the same capture time.
+    pub fn frame_at(&self, f: u32, max_frames: u32) -> u32 {
+        match self.frames >= max_frames {
+            true => f,
+            false => (f - 1) * self.frames / max_frames.max(1) + 1,
+        }
+    }
+
     /// Return a string capture file name with the given frame number.
     pub fn with_frame(&self, f: u32) -> String {
         let Self {
@@ -253,6 +633,7 @@ impl OutTemplate {
             seconds,
             second_w,
             frame_w,
+            ..
         } = self;
         format!("{prefix}-{seconds:0second_w$}s-{f:0frame_w$}.bmp")
     }
@@ -265,7 +646,93 @@ impl fmt::Display for OutTemplate {
             seconds,
             second_w,
             frame_w,
+            ..
         } = self;
         write!(f, "{prefix}-{seconds:0second_w$}s-%0{frame_w}d.bmp")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn starts(args: &[&str], video_duration_s: f32) -> Vec<f32> {
+        let args = [&["extract"], args, &["video.mkv"]].concat();
+        Extract::try_parse_from(args)
+            .unwrap()
+            .capture_starts(video_duration_s)
+            .unwrap()
+    }
+
+    #[test]
+    fn capture_starts_number() {
+        assert_eq!(starts(&["-n", "4"], 100.0), [12.5, 37.5, 62.5, 87.5]);
+        assert_eq!(
+            starts(&["-n", "2", "--ignore-start", "20s"], 100.0),
+            [40.0, 80.0]
+        );
+    }
+
+    #[test]
+    fn capture_starts_interval_within_ignore_end() {
+        let starts = starts(&["-i", "10s", "--ignore-end", "20s"], 105.0);
+        assert_eq!(
+            starts,
+            [5.0, 15.0, 25.0, 35.0, 45.0, 55.0, 65.0, 75.0, 83.5]
+        );
+    }
+
+    #[test]
+    fn capture_starts_interval_partial_last() {
+        assert_eq!(starts(&["-i", "10s"], 19.0), [5.0, 15.0]);
+        let starts = starts(&["-i", "10s"], 105.0);
+        assert_eq!(starts.len(), 11);
+        assert_eq!(starts[10], 103.5);
+    }
+
+    #[test]
+    fn capture_starts_interval_longer_than_video() {
+        assert_eq!(starts(&["-i", "30s"], 10.0), [8.5]);
+    }
+
+    #[test]
+    fn claims_avoid_colliding_captures() {
+        // captures at 10s & 11s, the last clamped onto 11s too
+        let claims = Claims::new([10, 11, 11]);
+
+        // a failed capture may only remove files it doesn't share
+        assert!(claims.is_sole(10));
+        assert!(!claims.is_sole(11));
+
+        // shifting or nudging 10s onto a neighbour is refused
+        assert!(!claims.claim(11));
+        assert!(claims.claim(12));
+
+        // 11s frames are still used by the other capture after one moves
+        assert!(!claims.release(11));
+        assert!(claims.release(11));
+        assert!(claims.claim(11));
+
+        // a moved capture releases its original
+        assert!(claims.release(10));
+        assert!(claims.claim(10));
+    }
+
+    #[test]
+    fn frame_at_spreads_fewer_frames() {
+        let tmpl = OutTemplate::new("v", 10, 100, 30, 10);
+        let frames: Vec<_> = (1..=30).map(|f| tmpl.frame_at(f, 30)).collect();
+        // each of the 10 frames shows for 3 of the 30
+        for f in 1..=10 {
+            assert_eq!(frames.iter().filter(|n| **n == f).count(), 3, "frame {f}");
+        }
+        assert!(frames.is_sorted());
+
+        let single = OutTemplate::new("v", 10, 100, 30, 1);
+        assert!((1..=30).all(|f| single.frame_at(f, 30) == 1));
+
+        let full = OutTemplate::new("v", 10, 100, 30, 30);
+        assert!((1..=30).all(|f| full.frame_at(f, 30) == f));
+    }
+}
@@ -1,7 +1,8 @@
 pub mod label;
+pub mod watermark;
 
 use anyhow::anyhow;
-use image::GenericImage;
+use image::{GenericImage, Pixel};
 use rayon::prelude::*;
 use std::path::{Path, PathBuf};
 
@@ -28,55 +29,68 @@ pub struct Join {
     #[arg(long)]
     pub label: Vec<String>,
 
+    /// Text drawn centred near the bottom of each capture, e.g. subtitles.
+    #[arg(long)]
+    pub caption: Vec<String>,
+
+    /// Position `0..=1` of each capture within the video, drawn as a thin progress
+    /// bar along the bottom edge of the capture's cell.
+    #[arg(long)]
+    pub progress: Vec<f32>,
+
+    /// Index of a capture to render larger, at the top-left of the grid.
+    #[arg(long)]
+    pub hero: Option<usize>,
+
+    /// Number of columns & rows the --hero capture spans.
+    #[arg(long, default_value_t = 2)]
+    pub hero_span: u32,
+
+    /// Text drawn in a header strip above the grid.
+    #[arg(long)]
+    pub header: Option<String>,
+
+    /// Images drawn in strips below the grid, in order. Each is resized to the grid width.
+    #[arg(long)]
+    pub footer: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    pub watermark: watermark::Watermark,
+
+    /// Font file used to draw text, before the embedded & system fallback fonts.
+    /// May be specified multiple times to form a fallback chain.
+    #[arg(long)]
+    pub font: Vec<PathBuf>,
+
     /// Images to join.
     #[arg(required = true)]
     pub capture_images: Vec<PathBuf>,
 }
 
-impl Join {
-    pub fn run(&self) -> anyhow::Result<()> {
-        let Self {
-            columns,
-            output,
-            capture_images,
-            ..
-        } = self;
-
-        let n_captures = capture_images.len() as u32;
-
-        // load images concurrently
-        let images: Vec<_> = capture_images
-            .par_iter()
-            .map(|i| self.load_image(i).map_err(|e| anyhow!("{i:?}: {e}")))
-            .collect::<Result<Vec<_>, _>>()?;
-
-        let (cap_w, cap_h) = (images[0].width(), images[0].height());
-        let (rows, cols) = if *columns == 0 || n_captures <= *columns {
-            (1, n_captures)
-        } else {
-            let images = n_captures;
-            let mut rows = images / columns;
-            if !images.is_multiple_of(*columns) {
-                rows += 1;
-            }
-            (rows, *columns)
-        };
-
-        let mut labels = self.label.clone();
-        labels.resize_with(images.len(), String::new);
-
-        let mut all = image::RgbaImage::new(cap_w * cols, cap_h * rows);
-        for (idx, (img, label)) in images.into_iter().zip(labels).enumerate() {
-            let idx = idx as u32;
-            let x = (idx % cols) * cap_w;
-            let y = (idx / cols) * cap_h;
-            let img = label::draw(img, &label, &label::Config::default())?;
-            all.copy_from(&img, x as _, y as _)?;
+impl Default for Join {
+    fn default() -> Self {
+        Self {
+            columns: 0,
+            capture_width: None,
+            capture_height: None,
+            output: PathBuf::new(),
+            label: vec![],
+            caption: vec![],
+            progress: vec![],
+            hero: None,
+            hero_span: 2,
+            header: None,
+            footer: vec![],
+            watermark: <_>::default(),
+            font: vec![],
+            capture_images: vec![],
         }
+    }
+}
 
-        image::DynamicImage::from(all).into_rgb8().save(output)?;
-
-        Ok(())
+impl Join {
+    pub fn run(&self) -> anyhow::Result<JoinData> {
+        Compositor::default().compose(self)
     }
 
     fn load_image(&self, path: impl AsRef<Path>) -> anyhow::Result<image::DynamicImage> {
@@ -94,3 +108,455 @@ impl Join {
         Ok(img)
     }
 }
+
+/// Composes joins reusing the previous output's unchanged parts, for sequences
+/// of similar joins e.g. the frames of an animated sheet.
+///
+/// The header & watermark are prepared once & captures, with their labels,
+/// and footers are only redrawn when their inputs change.
+#[derive(Default)]
+pub struct Compositor {
+    /// Previous output, without the watermark.
+    canvas: image::RgbImage,
+    layout: Option<Layout>,
+    /// Inputs of each drawn capture.
+    drawn_cells: Vec<CellInput>,
+    /// Path & height of each drawn footer.
+    drawn_footers: Vec<(PathBuf, u32)>,
+    /// Position & image of the watermark layer.
+    watermark: Option<(u32, u32, image::RgbaImage)>,
+}
+
+/// Everything affecting the static parts of the output.
+#[derive(Debug, PartialEq)]
+struct Layout {
+    capture_size: (u32, u32),
+    captures: usize,
+    columns: u32,
+    hero: Option<usize>,
+    hero_span: u32,
+    header: Option<String>,
+    footer_heights: Vec<u32>,
+    watermark: watermark::Watermark,
+}
+
+/// Everything affecting how a single capture is drawn.
+#[derive(Debug, Clone, PartialEq)]
+struct CellInput {
+    path: PathBuf,
+    label: String,
+    caption: String,
+    progress: Option<f32>,
+}
+
+impl Compositor {
+    /// Write `join` output, only drawing what has changed since the last call.
+    pub fn compose(&mut self, join: &Join) -> anyhow::Result<JoinData> {
+        self.compose_with_footers(join, &[])
+    }
+
+    /// Write `join` output with in-memory `footers` drawn below the footer
+    /// files, e.g. a per-frame timeline. These are drawn on every call.
+    pub fn compose_with_footers(
+        &mut self,
+        join: &Join,
+        footers: &[image::RgbImage],
+    ) -> anyhow::Result<JoinData> {
+        label::init_fonts(&join.font)?;
+
+        let n = join.capture_images.len();
+        let inputs: Vec<_> = (0..n)
+            .map(|idx| CellInput {
+                path: join.capture_images[idx].clone(),
+                label: join.label.get(idx).cloned().unwrap_or_default(),
+                caption: join.caption.get(idx).cloned().unwrap_or_default(),
+                progress: join.progress.get(idx).copied(),
+            })
+            .collect();
+
+        // load changed images concurrently
+        let mut images: Vec<_> = inputs
+            .par_iter()
+            .enumerate()
+            .map(
+                |(idx, input)| match self.drawn_cells.get(idx) == Some(input) {
+                    true => Ok(None),
+                    false => join
+                        .load_image(&input.path)
+                        .map_err(|e| anyhow!("{:?}: {e}", input.path))
+                        .map(Some),
+                },
+            )
+            .collect::<anyhow::Result<_>>()?;
+
+        let capture_size = match (&images[0], &self.layout) {
+            (Some(img), _) => (img.width(), img.height()),
+            (None, Some(layout)) => layout.capture_size,
+            (None, None) => unreachable!("first capture is always loaded"),
+        };
+        let (cap_w, cap_h) = capture_size;
+        let hero = join.hero.filter(|h| *h < n && join.hero_span > 1);
+        let slots = grid_slots(n, join.columns, hero, join.hero_span);
+        let (cols, rows) = grid_size(n, join.columns, hero, join.hero_span);
+        let grid_w = cap_w * cols;
+
+        let extra_footers = footers;
+        let mut footers: Vec<_> = join
+            .footer
+            .iter()
+            .enumerate()
+            .map(|(idx, path)| match self.drawn_footers.get(idx) {
+                Some((drawn, _)) if drawn == path => Ok(None),
+                _ => load_footer(path, grid_w).map(Some),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let footer_heights: Vec<_> = footers
+            .iter()
+            .enumerate()
+            .map(|(idx, footer)| match footer {
+                Some(footer) => footer.height(),
+                None => self.drawn_footers[idx].1,
+            })
+            .chain(extra_footers.iter().map(|f| f.height()))
+            .collect();
+
+        let header_h = match &join.header {
+            Some(_) => (cap_h / 5).max(16),
+            None => 0,
+        };
+
+        let layout = Layout {
+            capture_size,
+            captures: n,
+            columns: join.columns,
+            hero,
+            hero_span: join.hero_span,
+            header: join.header.clone(),
+            footer_heights,
+            watermark: join.watermark.clone(),
+        };
+        if self.layout.as_ref() != Some(&layout) {
+            // redraw everything
+            for (img, input) in images.iter_mut().zip(&inputs) {
+                if img.is_none() {
+                    *img = Some(
+                        join.load_image(&input.path)
+                            .map_err(|e| anyhow!("{:?}: {e}", input.path))?,
+                    );
+                }
+            }
+            for (footer, path) in footers.iter_mut().zip(&join.footer) {
+                if footer.is_none() {
+                    *footer = Some(load_footer(path, grid_w)?);
+                }
+            }
+
+            let footer_h: u32 = layout.footer_heights.iter().sum();
+            self.canvas = image::RgbImage::new(grid_w, cap_h * rows + header_h + footer_h);
+            if let Some(header) = &join.header {
+                let strip =
+                    image::RgbaImage::from_pixel(grid_w, header_h, image::Rgba([0, 0, 0, 255]));
+                let strip = label::draw(strip.into(), header, &label::Config::header())?;
+                self.canvas.copy_from(&strip.into_rgb8(), 0, 0)?;
+            }
+
+            let (width, height) = self.canvas.dimensions();
+            let mut watermark = image::RgbaImage::new(width, height);
+            join.watermark.apply(&mut watermark)?;
+            self.watermark = crop_drawn(&watermark);
+            self.layout = Some(layout);
+        }
+
+        // draw changed captures concurrently
+        let drawn: Vec<_> = images
+            .into_par_iter()
+            .zip(&inputs)
+            .zip(&slots)
+            .map(|((img, input), slot)| {
+                let Some(img) = img else { return Ok(None) };
+                let (width, height) = (cap_w * slot.span, cap_h * slot.span);
+                let img = match slot.span {
+                    1 => img,
+                    _ => img.resize_exact(width, height, image::imageops::FilterType::CatmullRom),
+                };
+                let img = match input.progress {
+                    Some(progress) => draw_progress(img, progress),
+                    None => img,
+                };
+                let img = label::draw(img, &input.caption, &label::Config::caption())?;
+                let img = label::draw(img, &input.label, &label::Config::default())?;
+                Ok(Some(img.into_rgb8()))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let mut cells = Vec::with_capacity(n);
+        for (img, slot) in drawn.into_iter().zip(&slots) {
+            let x = slot.col * cap_w;
+            let y = slot.row * cap_h + header_h;
+            if let Some(img) = img {
+                self.canvas.copy_from(&img, x, y)?;
+            }
+            cells.push(Cell {
+                x,
+                y,
+                width: cap_w * slot.span,
+                height: cap_h * slot.span,
+            });
+        }
+        self.drawn_cells = inputs;
+
+        let mut footer_y = header_h + cap_h * rows;
+        let footer_heights = self.layout.iter().flat_map(|l| &l.footer_heights);
+        self.drawn_footers = join
+            .footer
+            .iter()
+            .cloned()
+            .zip(footer_heights.copied())
+            .collect();
+        for (footer, (_, height)) in footers.into_iter().zip(&self.drawn_footers) {
+            if let Some(footer) = footer {
+                self.canvas.copy_from(&footer, 0, footer_y)?;
+            }
+            footer_y += height;
+        }
+        for footer in extra_footers {
+            match footer.width() == grid_w {
+                true => self.canvas.copy_from(footer, 0, footer_y)?,
+                false => {
+                    let footer = image::imageops::resize(
+                        footer,
+                        grid_w,
+                        footer.height(),
+                        image::imageops::FilterType::Triangle,
+                    );
+                    self.canvas.copy_from(&footer, 0, footer_y)?;
+                }
+            }
+            footer_y += footer.height();
+        }
+
+        let (width, height) = self.canvas.dimensions();
+        match &self.watermark {
+            None => self.canvas.save(&join.output)?,
+            Some((x, y, watermark)) => {
+                let mut out = self.canvas.clone();
+                blend_onto(&mut out, watermark, *x, *y);
+                out.save(&join.output)?;
+            }
+        }
+
+        Ok(JoinData {
+            width,
+            height,
+            columns: cols,
+            rows,
+            cells,
+        })
+    }
+}
+
+/// Load a footer image resized to `width`.
+fn load_footer(path: &Path, width: u32) -> anyhow::Result<image::RgbImage> {
+    let img = image::ImageReader::open(path)
+        .and_then(|r| r.decode().map_err(std::io::Error::other))
+        .map_err(|e| anyhow!("{path:?}: {e}"))?;
+    Ok(match img.width() == width {
+        true => img.into_rgb8(),
+        false => img
+            .resize_exact(width, img.height(), image::imageops::FilterType::Triangle)
+            .into_rgb8(),
+    })
+}
+
+/// Alpha blend `layer` onto `img` at `x`, `y`.
+fn blend_onto(img: &mut image::RgbImage, layer: &image::RgbaImage, x: u32, y: u32) {
+    for (lx, ly, src) in layer.enumerate_pixels() {
+        let Some(dst) = img.get_pixel_mut_checked(x + lx, y + ly) else {
+            continue;
+        };
+        let alpha = f32::from(src.0[3]) / 255.0;
+        for (d, s) in dst.0.iter_mut().zip(src.0) {
+            *d = (f32::from(*d) * (1.0 - alpha) + f32::from(s) * alpha).round() as u8;
+        }
+    }
+}
+
+/// Crop to the drawn, non-transparent, pixels returning the offset & cropped image.
+fn crop_drawn(img: &image::RgbaImage) -> Option<(u32, u32, image::RgbaImage)> {
+    let (mut min, mut max) = ((img.width(), img.height()), (0, 0));
+    for (x, y, px) in img.enumerate_pixels() {
+        if px.0[3] != 0 {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x + 1), max.1.max(y + 1));
+        }
+    }
+    if min.0 >= max.0 || min.1 >= max.1 {
+        return None;
+    }
+    let cropped = image::imageops::crop_imm(img, min.0, min.1, max.0 - min.0, max.1 - min.1);
+    Some((min.0, min.1, cropped.to_image()))
+}
+
+/// Grid position of a capture.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    col: u32,
+    row: u32,
+    /// Number of columns & rows covered.
+    span: u32,
+}
+
+/// Draw a bar along the bottom edge filled to `progress` in the range `0..=1`.
+fn draw_progress(img: image::DynamicImage, progress: f32) -> image::DynamicImage {
+    let mut rgba = img.into_rgba8();
+    let (w, h) = rgba.dimensions();
+    let bar_h = (h / 50).max(2).min(h);
+    let filled = (progress.clamp(0.0, 1.0) * w as f32).round() as u32;
+    for y in h - bar_h..h {
+        for x in 0..w {
+            let colour = match x < filled {
+                true => image::Rgba([230, 50, 50, 255]),
+                false => image::Rgba([0, 0, 0, 150]),
+            };
+            rgba.get_pixel_mut(x, y).blend(&colour);
+        }
+    }
+    rgba.into()
+}
+
+/// Number of columns & rows of a grid of `n` captures.
+pub fn grid_size(n: usize, columns: u32, hero: Option<usize>, hero_span: u32) -> (u32, u32) {
+    let hero = hero.filter(|h| *h < n && hero_span > 1);
+    let slots = grid_slots(n, columns, hero, hero_span);
+    let cols = slots.iter().map(|s| s.col + s.span).max().unwrap_or(0);
+    let rows = slots.iter().map(|s| s.row + s.span).max().unwrap_or(0);
+    (cols, rows)
+}
+
+/// Lay out `n` captures in row-major order with an optional larger hero capture
+/// occupying the top-left of the grid.
+fn grid_slots(n: usize, columns: u32, hero: Option<usize>, hero_span: u32) -> Vec<Slot> {
+    let mut columns = match columns {
+        0 => n as u32,
+        c => c,
+    };
+    if hero.is_some() {
+        columns = columns.max(hero_span);
+    }
+    let columns = columns as usize;
+
+    // occupied cells in row-major order
+    let mut taken: Vec<bool> = vec![];
+    if hero.is_some() {
+        let span = hero_span as usize;
+        taken.resize(span * columns, false);
+        for row in 0..span {
+            taken[row * columns..][..span].fill(true);
+        }
+    }
+
+    let mut next = 0;
+    (0..n)
+        .map(|idx| {
+            if Some(idx) == hero {
+                return Slot {
+                    col: 0,
+                    row: 0,
+                    span: hero_span,
+                };
+            }
+            while taken.get(next).copied().unwrap_or(false) {
+                next += 1;
+            }
+            let (col, row) = (next % columns, next / columns);
+            next += 1;
+            Slot {
+                col: col as _,
+                row: row as _,
+                span: 1,
+            }
+        })
+        .collect()
+}
+
+/// Layout of a joined grid image.
+#[derive(Debug, Clone)]
+pub struct JoinData {
+    pub width: u32,
+    pub height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    /// Pixel area of each capture in the output, in capture order.
+    pub cells: Vec<Cell>,
+}
+
+/// Pixel area of a single capture inside a joined grid image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Cell {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn missing_image_error_has_path() {
+        let join = Join::try_parse_from(["join", "-c", "2", "-o", "o.png", "missing-capture.bmp"])
+            .unwrap();
+        let err = join.run().unwrap_err().to_string();
+        assert!(err.starts_with("\"missing-capture.bmp\": "), "{err}");
+    }
+
+    #[test]
+    fn draw_progress_short_image() {
+        for h in 0..3 {
+            let img = image::RgbaImage::from_pixel(4, h, image::Rgba([0, 0, 0, 255]));
+            let img = draw_progress(img.into(), 0.5).into_rgba8();
+            if h > 0 {
+                assert_eq!(img.get_pixel(0, h - 1), &image::Rgba([230, 50, 50, 255]));
+                assert_eq!(img.get_pixel(3, 0), &image::Rgba([0, 0, 0, 255]));
+            }
+        }
+    }
+
+    fn slots(n: usize, columns: u32, hero: Option<usize>, hero_span: u32) -> Vec<(u32, u32, u32)> {
+        grid_slots(n, columns, hero, hero_span)
+            .iter()
+            .map(|s| (s.col, s.row, s.span))
+            .collect()
+    }
+
+    #[test]
+    fn grid_without_hero() {
+        assert_eq!(
+            slots(5, 2, None, 2),
+            [(0, 0, 1), (1, 0, 1), (0, 1, 1), (1, 1, 1), (0, 2, 1)]
+        );
+        assert_eq!(grid_size(5, 2, None, 2), (2, 3));
+        // 0 columns puts everything in one row
+        assert_eq!(grid_size(4, 0, None, 2), (4, 1));
+    }
+
+    #[test]
+    fn grid_hero_fills_around() {
+        // hero in the middle of capture order is still drawn top-left
+        assert_eq!(
+            slots(4, 3, Some(1), 2),
+            [(2, 0, 1), (0, 0, 2), (2, 1, 1), (0, 2, 1)]
+        );
+        assert_eq!(grid_size(4, 3, Some(1), 2), (3, 3));
+    }
+
+    #[test]
+    fn grid_hero_widens_columns() {
+        assert_eq!(grid_size(3, 1, Some(0), 3), (3, 4));
+        // out of range hero & span 1 are ignored
+        assert_eq!(grid_size(3, 1, Some(3), 3), (1, 3));
+        assert_eq!(grid_size(3, 1, Some(0), 1), (1, 3));
+    }
+}
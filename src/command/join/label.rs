@@ -1,17 +1,65 @@
+use anyhow::Context;
 use glyph_brush_layout::{
-    GlyphPositioner, HorizontalAlign, SectionGeometry, SectionText, VerticalAlign,
-    ab_glyph::{Font, FontRef, PxScale, Rect, point},
+    FontId, GlyphPositioner, HorizontalAlign, SectionGeometry, SectionText, VerticalAlign,
+    ab_glyph::{Font, FontArc, PxScale, Rect, ScaleFont, point},
 };
 use image::Pixel;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex, OnceLock},
+};
 
 const CANTARELL: &[u8] = include_bytes!("Cantarell-Regular.ttf");
 
+/// Fonts tried, in order, for glyphs missing from the user & embedded fonts.
+const SYSTEM_FALLBACK_FONTS: &[&str] = &[
+    // linux
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSans-Regular.ttf",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto/NotoSansArabic-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansArabic-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansHebrew-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansHebrew-Regular.ttf",
+    "/usr/share/fonts/noto/NotoSansSymbols2-Regular.ttf",
+    "/usr/share/fonts/truetype/noto/NotoSansSymbols2-Regular.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    // macos
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    // windows
+    r"C:\Windows\Fonts\msyh.ttc",
+    r"C:\Windows\Fonts\YuGothR.ttc",
+    r"C:\Windows\Fonts\malgun.ttf",
+    r"C:\Windows\Fonts\arial.ttf",
+    r"C:\Windows\Fonts\seguisym.ttf",
+    r"C:\Windows\Fonts\seguiemj.ttf",
+];
+
+/// Maximum number of cached rendered labels.
+const MAX_STAMPS: usize = 4096;
+
+static FONTS: OnceLock<Fonts> = OnceLock::new();
+/// Rendered labels, as the same labels are drawn on every frame of animated sheets.
+static STAMPS: LazyLock<Mutex<HashMap<StampKey, Arc<Stamp>>>> = LazyLock::new(<_>::default);
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub scale_percent: f32,
     pub margin_percent: f32,
     pub padding_percent: f32,
     pub background_opacity: f32,
+    pub h_align: HorizontalAlign,
+    pub v_align: VerticalAlign,
+    /// Text is wrapped within the image width into at most this many lines,
+    /// further text is truncated with an ellipsis.
+    pub max_lines: usize,
 }
 
 impl Default for Config {
@@ -21,49 +69,171 @@ impl Default for Config {
             margin_percent: 0.01,
             padding_percent: 0.01,
             background_opacity: 0.7,
+            h_align: HorizontalAlign::Right,
+            v_align: VerticalAlign::Bottom,
+            max_lines: 1,
+        }
+    }
+}
+
+impl Config {
+    /// Centred text above the bottom edge, for subtitles.
+    pub fn caption() -> Self {
+        Self {
+            scale_percent: 0.07,
+            margin_percent: 0.12,
+            padding_percent: 0.01,
+            background_opacity: 0.6,
+            h_align: HorizontalAlign::Center,
+            v_align: VerticalAlign::Bottom,
+            max_lines: 2,
+        }
+    }
+
+    /// Large left aligned text without a background, for header strips.
+    pub fn header() -> Self {
+        Self {
+            scale_percent: 0.6,
+            margin_percent: 0.2,
+            padding_percent: 0.0,
+            background_opacity: 0.0,
+            h_align: HorizontalAlign::Left,
+            v_align: VerticalAlign::Center,
+            max_lines: 1,
         }
     }
 }
 
+/// Use `user` font files, in order, before the embedded & system fonts.
+///
+/// Only the first call has an effect & must happen before any labels are drawn.
+pub fn init_fonts(user: &[PathBuf]) -> anyhow::Result<()> {
+    if FONTS.get().is_none() {
+        _ = FONTS.set(Fonts::load(user)?);
+    }
+    Ok(())
+}
+
 pub fn draw(
     img: image::DynamicImage,
     label: &str,
     conf: &Config,
 ) -> anyhow::Result<image::DynamicImage> {
-    if label.is_empty() {
+    if label.trim().is_empty() {
         return Ok(img);
     }
 
-    let (imgw, imgh) = (img.width() as f32, img.height() as f32);
+    let key = StampKey::new(label, img.width(), img.height(), conf);
+    let cached = STAMPS.lock().unwrap().get(&key).cloned();
+    let stamp = match cached {
+        Some(stamp) => stamp,
+        None => {
+            let stamp = Arc::new(render(label, img.width(), img.height(), conf)?);
+            let mut stamps = STAMPS.lock().unwrap();
+            if stamps.len() >= MAX_STAMPS {
+                stamps.clear();
+            }
+            stamps.insert(key, stamp.clone());
+            stamp
+        }
+    };
+
+    let mut rgba = img.into_rgba8();
+    image::imageops::overlay(&mut rgba, &stamp.img, stamp.x.into(), stamp.y.into());
+    Ok(rgba.into())
+}
+
+/// Rendered label & its position, to be blended onto images of the same size.
+struct Stamp {
+    x: u32,
+    y: u32,
+    img: image::RgbaImage,
+}
+
+/// Everything affecting how a label renders.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct StampKey {
+    label: String,
+    width: u32,
+    height: u32,
+    /// Config f32 values as bits.
+    conf: [u32; 4],
+    align: (HorizontalAlign, VerticalAlign),
+    max_lines: usize,
+}
+
+impl StampKey {
+    fn new(label: &str, width: u32, height: u32, conf: &Config) -> Self {
+        Self {
+            label: label.to_owned(),
+            width,
+            height,
+            conf: [
+                conf.scale_percent.to_bits(),
+                conf.margin_percent.to_bits(),
+                conf.padding_percent.to_bits(),
+                conf.background_opacity.to_bits(),
+            ],
+            align: (conf.h_align, conf.v_align),
+            max_lines: conf.max_lines,
+        }
+    }
+}
+
+/// Render `label` onto a transparent `width` x `height` layer, cropped to the drawn area.
+fn render(label: &str, width: u32, height: u32, conf: &Config) -> anyhow::Result<Stamp> {
+    let (imgw, imgh) = (width as f32, height as f32);
     let min_dim = imgw.min(imgh);
-    let font = FontRef::try_from_slice(CANTARELL)?;
+    let fonts = fonts().for_text(label);
     let scale = PxScale::from(min_dim * conf.scale_percent);
     let margin = min_dim * conf.margin_percent;
     let pad = min_dim * conf.padding_percent;
 
-    let layout = glyph_brush_layout::Layout::default_single_line()
-        .v_align(VerticalAlign::Bottom)
-        .h_align(HorizontalAlign::Right);
+    let max_width = imgw - margin * 4.0 - pad * 2.0;
+    let lines = wrap(label, max_width, conf.max_lines, |s| {
+        text_width(fonts, scale, s)
+    });
+    let text = lines
+        .iter()
+        .map(|line| visual_order(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let runs = font_runs(fonts, &text);
+
+    let layout = glyph_brush_layout::Layout::default_wrap()
+        .v_align(conf.v_align)
+        .h_align(conf.h_align);
     let geometry = SectionGeometry {
-        screen_position: (imgw - margin * 2.0, imgh - margin),
+        screen_position: (
+            match conf.h_align {
+                HorizontalAlign::Left => margin * 2.0,
+                HorizontalAlign::Center => imgw / 2.0,
+                HorizontalAlign::Right => imgw - margin * 2.0,
+            },
+            match conf.v_align {
+                VerticalAlign::Top => margin,
+                VerticalAlign::Center => imgh / 2.0,
+                VerticalAlign::Bottom => imgh - margin,
+            },
+        ),
         bounds: (imgw, imgh),
     };
 
-    let glyphs = layout.calculate_glyphs(
-        &[&font],
-        &geometry,
-        &[SectionText {
-            text: label,
+    let sections: Vec<_> = runs
+        .iter()
+        .map(|(text, font_id)| SectionText {
+            text,
             scale,
-            ..<_>::default()
-        }],
-    );
+            font_id: *font_id,
+        })
+        .collect();
+    let glyphs = layout.calculate_glyphs(fonts, &geometry, &sections);
 
-    let mut rgba = img.into_rgba8();
+    let mut rgba = image::RgbaImage::new(width, height);
 
     let outline_glyphs: Vec<_> = glyphs
         .into_iter()
-        .filter_map(|g| font.outline_glyph(g.glyph))
+        .filter_map(|g| fonts[g.font_id.0].outline_glyph(g.glyph))
         .collect();
 
     // label background
@@ -94,9 +264,9 @@ pub fn draw(
         })
     {
         let max_x = (b.max.x.ceil() as u32).min(rgba.width() - 1);
-        let min_x = b.min.x as u32;
+        let min_x = b.min.x.max(0.0) as u32;
         let max_y = (b.max.y.ceil() as u32).min(rgba.height() - 1);
-        let min_y = b.min.y as u32;
+        let min_y = b.min.y.max(0.0) as u32;
 
         for x in min_x..=max_x {
             for y in min_y..=max_y {
@@ -118,12 +288,18 @@ pub fn draw(
     for glyph in outline_glyphs {
         let bounds = glyph.px_bounds();
         glyph.draw(|x, y, c| {
-            let px = rgba.get_pixel_mut(x + bounds.min.x as u32, y + bounds.min.y as u32);
-            px.blend(&image::Rgba([255, 255, 255, (c * 255.0) as u8]));
+            let x = x as i64 + bounds.min.x as i64;
+            let y = y as i64 + bounds.min.y as i64;
+            if let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y))
+                && let Some(px) = rgba.get_pixel_mut_checked(x, y)
+            {
+                px.blend(&image::Rgba([255, 255, 255, (c * 255.0) as u8]));
+            }
         });
     }
 
-    Ok(rgba.into())
+    let (x, y, img) = super::crop_drawn(&rgba).unwrap_or((0, 0, image::RgbaImage::new(0, 0)));
+    Ok(Stamp { x, y, img })
 }
 
 pub fn seconds_text(seconds: u32) -> String {
@@ -135,3 +311,205 @@ pub fn seconds_text(seconds: u32) -> String {
         (h, m, s) => format!("{h}:{m:02}:{s:02}"),
     }
 }
+
+/// Font fallback chain.
+struct Fonts {
+    /// User fonts then the embedded font.
+    primary: Vec<FontArc>,
+    /// `primary` followed by system fallback fonts, loaded on first use.
+    with_fallback: OnceLock<Vec<FontArc>>,
+}
+
+impl Fonts {
+    fn load(user: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut primary = user
+            .iter()
+            .map(|path| load_font(path).with_context(|| format!("invalid font {path:?}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        primary.push(FontArc::try_from_slice(CANTARELL)?);
+        Ok(Self {
+            primary,
+            with_fallback: OnceLock::new(),
+        })
+    }
+
+    /// Fonts to lay out `text`, only including system fallbacks if necessary.
+    fn for_text(&self, text: &str) -> &[FontArc] {
+        let covered = text
+            .chars()
+            .filter(|c| !c.is_whitespace() && !c.is_control())
+            .all(|c| self.primary.iter().any(|f| f.glyph_id(c).0 != 0));
+        if covered {
+            return &self.primary;
+        }
+        self.with_fallback.get_or_init(|| {
+            let system = SYSTEM_FALLBACK_FONTS
+                .iter()
+                .filter_map(|path| load_font(Path::new(path)).ok());
+            self.primary.iter().cloned().chain(system).collect()
+        })
+    }
+}
+
+fn fonts() -> &'static Fonts {
+    FONTS.get_or_init(|| Fonts::load(&[]).expect("invalid embedded font"))
+}
+
+fn load_font(path: &Path) -> anyhow::Result<FontArc> {
+    let data = fs::read(path)?;
+    // use the first font of collections
+    let font = glyph_brush_layout::ab_glyph::FontVec::try_from_vec_and_index(data, 0)?;
+    Ok(FontArc::new(font))
+}
+
+/// Index of the first font with a glyph for `c`, otherwise 0.
+fn font_for(fonts: &[FontArc], c: char) -> FontId {
+    FontId(fonts.iter().position(|f| f.glyph_id(c).0 != 0).unwrap_or(0))
+}
+
+/// Split `text` into runs of chars using the same font.
+fn font_runs(fonts: &[FontArc], text: &str) -> Vec<(String, FontId)> {
+    let mut runs: Vec<(String, FontId)> = vec![];
+    for c in text.chars() {
+        // whitespace stays in the current run
+        let font_id = match runs.last() {
+            Some((_, id)) if c.is_whitespace() => *id,
+            _ => font_for(fonts, c),
+        };
+        match runs.last_mut() {
+            Some((run, id)) if *id == font_id => run.push(c),
+            _ => runs.push((c.into(), font_id)),
+        }
+    }
+    runs
+}
+
+/// Pixel width of a single line of text.
+fn text_width(fonts: &[FontArc], scale: PxScale, text: &str) -> f32 {
+    let mut width = 0.0;
+    let mut prev = None;
+    for c in text.chars() {
+        let font_id = font_for(fonts, c);
+        let font = fonts[font_id.0].as_scaled(scale);
+        let glyph = font.glyph_id(c);
+        if let Some((prev_font, prev_glyph)) = prev
+            && prev_font == font_id
+        {
+            width += font.kern(prev_glyph, glyph);
+        }
+        width += font.h_advance(glyph);
+        prev = Some((font_id, glyph));
+    }
+    width
+}
+
+/// Wrap `text` into lines no wider than `max_width`, breaking on spaces or,
+/// for long words & scripts without spaces, between chars.
+///
+/// Text beyond `max_lines` is truncated with an ellipsis.
+fn wrap(text: &str, max_width: f32, max_lines: usize, width: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = match line.is_empty() {
+                true => word.to_owned(),
+                false => format!("{line} {word}"),
+            };
+            if width(&candidate) <= max_width {
+                line = candidate;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(line);
+            }
+            line = word.to_owned();
+            while width(&line) > max_width && line.chars().count() > 1 {
+                let fits = (1..line.chars().count())
+                    .rev()
+                    .map(|n| line.char_indices().nth(n).map_or(line.len(), |(i, _)| i))
+                    .find(|i| width(&line[..*i]) <= max_width)
+                    .unwrap_or_else(|| line.chars().next().map_or(0, char::len_utf8));
+                lines.push(line[..fits].to_owned());
+                line = line[fits..].to_owned();
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+
+    if lines.len() > max_lines.max(1) {
+        lines.truncate(max_lines.max(1));
+        let last = lines.last_mut().expect("non-empty lines");
+        loop {
+            let truncated = format!("{}…", last.trim_end());
+            if width(&truncated) <= max_width || last.is_empty() {
+                *last = truncated;
+                break;
+            }
+            last.pop();
+        }
+    }
+    lines
+}
+
+/// Reorder a line of logical order text, that may contain right-to-left
+/// scripts, into left-to-right display order.
+///
+/// Note: Letters are not shaped, e.g. arabic letters display in isolated forms.
+fn visual_order(line: &str) -> String {
+    let bidi = unicode_bidi::BidiInfo::new(line, None);
+    match (bidi.has_rtl(), bidi.paragraphs.first()) {
+        (true, Some(para)) => bidi.reorder_line(para, para.range.clone()).into_owned(),
+        _ => line.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> f32 {
+        text.chars().count() as f32
+    }
+
+    #[test]
+    fn wrap_words() {
+        assert_eq!(
+            wrap("the quick brown fox", 10.0, 5, chars),
+            ["the quick", "brown fox"]
+        );
+        assert_eq!(wrap("a\nb", 10.0, 5, chars), ["a", "b"]);
+    }
+
+    #[test]
+    fn wrap_long_word() {
+        assert_eq!(wrap("abcdefghij", 4.0, 5, chars), ["abcd", "efgh", "ij"]);
+        // scripts without spaces
+        assert_eq!(
+            wrap("字幕のテキスト", 3.0, 5, chars),
+            ["字幕の", "テキス", "ト"]
+        );
+    }
+
+    #[test]
+    fn wrap_ellipsis() {
+        assert_eq!(wrap("aa bb cc dd", 5.0, 1, chars), ["aa b…"]);
+        assert_eq!(wrap("aa bb cc dd", 8.0, 2, chars), ["aa bb cc", "dd"]);
+    }
+
+    #[test]
+    fn visual_order_rtl() {
+        assert_eq!(visual_order("abc"), "abc");
+        assert_eq!(visual_order("שלום"), "םולש");
+        // numbers following rtl text join its run
+        assert_eq!(visual_order("hi שלום 12"), "hi 12 םולש");
+    }
+
+    #[test]
+    fn seconds_text_hours() {
+        assert_eq!(seconds_text(65), "01:05");
+        assert_eq!(seconds_text(3725), "1:02:05");
+    }
+}
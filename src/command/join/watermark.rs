@@ -0,0 +1,111 @@
+use crate::command::label;
+use anyhow::Context;
+use glyph_brush_layout::{HorizontalAlign, VerticalAlign};
+use std::path::PathBuf;
+
+/// Logo image and/or text composited onto the joined sheet.
+#[derive(clap::Args, Debug, Clone, PartialEq)]
+pub struct Watermark {
+    /// Watermark image drawn onto the sheet, e.g. a logo png with transparency.
+    #[arg(long)]
+    pub watermark: Option<PathBuf>,
+
+    /// Watermark text drawn onto the sheet.
+    #[arg(long)]
+    pub watermark_text: Option<String>,
+
+    /// Watermark position.
+    #[arg(long, value_enum, default_value_t = Position::BottomRight)]
+    pub watermark_position: Position,
+
+    /// Watermark opacity 0-1.
+    #[arg(long, default_value_t = 0.8)]
+    pub watermark_opacity: f32,
+
+    /// Watermark image width as a fraction of the sheet width.
+    #[arg(long, default_value_t = 0.15)]
+    pub watermark_scale: f32,
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Self {
+            watermark: None,
+            watermark_text: None,
+            watermark_position: Position::BottomRight,
+            watermark_opacity: 0.8,
+            watermark_scale: 0.15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl Watermark {
+    /// Composite the watermark image & text onto `img`.
+    pub fn apply(&self, img: &mut image::RgbaImage) -> anyhow::Result<()> {
+        let (w, h) = img.dimensions();
+        let margin = (w.min(h) as f32 * 0.02) as i64;
+
+        if let Some(path) = &self.watermark {
+            let logo = image::ImageReader::open(path)?
+                .decode()
+                .with_context(|| format!("{path:?}"))?;
+            let logo_w = ((w as f32 * self.watermark_scale) as u32).max(1);
+            let mut logo = logo
+                .resize(logo_w, u32::MAX, image::imageops::FilterType::CatmullRom)
+                .into_rgba8();
+            self.fade(&mut logo);
+
+            let (lw, lh) = (i64::from(logo.width()), i64::from(logo.height()));
+            let (w, h) = (i64::from(w), i64::from(h));
+            let (x, y) = match self.watermark_position {
+                Position::TopLeft => (margin, margin),
+                Position::TopRight => (w - lw - margin, margin),
+                Position::BottomLeft => (margin, h - lh - margin),
+                Position::BottomRight => (w - lw - margin, h - lh - margin),
+                Position::Center => ((w - lw) / 2, (h - lh) / 2),
+            };
+            image::imageops::overlay(img, &logo, x, y);
+        }
+
+        if let Some(text) = &self.watermark_text {
+            let (h_align, v_align) = match self.watermark_position {
+                Position::TopLeft => (HorizontalAlign::Left, VerticalAlign::Top),
+                Position::TopRight => (HorizontalAlign::Right, VerticalAlign::Top),
+                Position::BottomLeft => (HorizontalAlign::Left, VerticalAlign::Bottom),
+                Position::BottomRight => (HorizontalAlign::Right, VerticalAlign::Bottom),
+                Position::Center => (HorizontalAlign::Center, VerticalAlign::Center),
+            };
+            let conf = label::Config {
+                scale_percent: 0.05,
+                margin_percent: 0.02,
+                padding_percent: 0.0,
+                background_opacity: 0.0,
+                h_align,
+                v_align,
+                max_lines: 1,
+            };
+            // draw onto a transparent layer so the text opacity can be applied
+            let layer = image::RgbaImage::new(w, h);
+            let mut layer = label::draw(layer.into(), text, &conf)?.into_rgba8();
+            self.fade(&mut layer);
+            image::imageops::overlay(img, &layer, 0, 0);
+        }
+        Ok(())
+    }
+
+    fn fade(&self, img: &mut image::RgbaImage) {
+        let opacity = self.watermark_opacity.clamp(0.0, 1.0);
+        for px in img.pixels_mut() {
+            px.0[3] = (f32::from(px.0[3]) * opacity).round() as u8;
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Simple image measurements used to rank captures.
+use std::path::Path;
+
+/// Pixel width images are reduced to before measuring.
+const MEASURE_W: u32 = 128;
+
+/// Load an image reduced & converted to luma for measuring.
+pub fn load_luma(path: &Path) -> anyhow::Result<image::GrayImage> {
+    let img = image::ImageReader::open(path)?.decode()?;
+    Ok(img.thumbnail(MEASURE_W, u32::MAX).into_luma8())
+}
+
+/// Mean absolute luma difference between two same-sized images in the range `0..=1`.
+pub fn difference(a: &image::GrayImage, b: &image::GrayImage) -> f32 {
+    let total: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(a, b)| u64::from(a.abs_diff(*b)))
+        .sum();
+    total as f32 / (a.as_raw().len().max(1) as f32 * 255.0)
+}
+
+/// Whether no channel of two same-sized images differs by more than `tolerance`.
+pub fn within_tolerance(a: &image::RgbImage, b: &image::RgbImage, tolerance: u8) -> bool {
+    a.dimensions() == b.dimensions()
+        && a.as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .all(|(a, b)| a.abs_diff(*b) <= tolerance)
+}
+
+/// Load an image reduced for measuring.
+pub fn load_rgb(path: &Path) -> anyhow::Result<image::RgbImage> {
+    let img = image::ImageReader::open(path)?.decode()?;
+    Ok(img.thumbnail(MEASURE_W * 2, u32::MAX).into_rgb8())
+}
+
+/// Variance of the laplacian, higher values indicate more in-focus detail.
+pub fn sharpness(luma: &image::GrayImage) -> f32 {
+    let (w, h) = luma.dimensions();
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    let px = |x: u32, y: u32| f32::from(luma.get_pixel(x, y).0[0]) / 255.0;
+
+    let laplacian: Vec<f32> = (1..h - 1)
+        .flat_map(|y| (1..w - 1).map(move |x| (x, y)))
+        .map(|(x, y)| px(x - 1, y) + px(x + 1, y) + px(x, y - 1) + px(x, y + 1) - 4.0 * px(x, y))
+        .collect();
+    let mean = laplacian.iter().sum::<f32>() / laplacian.len() as f32;
+    laplacian.iter().map(|l| (l - mean).powi(2)).sum::<f32>() / laplacian.len() as f32
+}
+
+/// How well exposed an image is in the range `0..=1`.
+///
+/// Penalises a mean brightness far from mid-grey & clipped shadows/highlights.
+pub fn exposure(luma: &image::GrayImage) -> f32 {
+    let len = luma.as_raw().len().max(1) as f32;
+    let mean = luma.as_raw().iter().map(|l| f32::from(*l)).sum::<f32>() / len / 255.0;
+    let clipped = luma
+        .as_raw()
+        .iter()
+        .filter(|l| !(8..=247).contains(*l))
+        .count() as f32
+        / len;
+    ((1.0 - (mean - 0.5).abs() * 2.0) * (1.0 - clipped)).max(0.0)
+}
+
+/// Hasler & Süsstrunk colourfulness metric, roughly `0..=1` for typical images.
+pub fn colourfulness(rgb: &image::RgbImage) -> f32 {
+    let len = rgb.pixels().len().max(1) as f32;
+    let (rg, yb): (Vec<f32>, Vec<f32>) = rgb
+        .pixels()
+        .map(|p| {
+            let [r, g, b] = p.0.map(f32::from);
+            (r - g, 0.5 * (r + g) - b)
+        })
+        .unzip();
+    let mean_sd = |v: &[f32]| {
+        let mean = v.iter().sum::<f32>() / len;
+        let var = v.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / len;
+        (mean, var.sqrt())
+    };
+    let (rg_mean, rg_sd) = mean_sd(&rg);
+    let (yb_mean, yb_sd) = mean_sd(&yb);
+    let c =
+        (rg_sd.powi(2) + yb_sd.powi(2)).sqrt() + 0.3 * (rg_mean.powi(2) + yb_mean.powi(2)).sqrt();
+    c / 150.0
+}
+
+/// Fraction of pixels within a typical YCbCr skin tone range.
+///
+/// A crude indication that people, and so faces, are in shot.
+pub fn skin(rgb: &image::RgbImage) -> f32 {
+    let len = rgb.pixels().len().max(1) as f32;
+    let skin = rgb
+        .pixels()
+        .filter(|p| {
+            let [r, g, b] = p.0.map(f32::from);
+            let cb = 128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b;
+            let cr = 128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b;
+            (77.0..=127.0).contains(&cb) && (133.0..=173.0).contains(&cr)
+        })
+        .count();
+    skin as f32 / len
+}
@@ -0,0 +1,281 @@
+use crate::{
+    command::{self, encode, label, sh_escape_filename},
+    temporary,
+};
+use anyhow::{Context, ensure};
+use rayon::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Create a short animated preview of a video.
+///
+/// Extracts captures, like vcs, and concatenates them in time rather
+/// than joining into a grid.
+#[derive(clap::Parser, Debug, Clone)]
+#[group(skip)]
+pub struct Preview {
+    /// Output file name. Defaults to input with .webp extension.
+    ///
+    /// The extension determines the format: avif, webp or mp4.
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Number of frames to crossfade between captures. 0 means hard cuts.
+    #[arg(long, default_value_t = 0)]
+    pub crossfade: u32,
+
+    /// Draw the timestamp of each capture.
+    #[arg(long, default_value_t = false)]
+    pub label: bool,
+
+    /// Output framerate.
+    #[arg(long, default_value_t = 20.0)]
+    pub fps: f32,
+
+    /// Crf quality level for encoding the output. Default depends on the codec.
+    #[arg(long)]
+    pub crf: Option<u8>,
+
+    /// Ffmpeg vcodec to use for encoding the output. Default depends on the format.
+    #[arg(long)]
+    pub codec: Option<String>,
+
+    /// Encoder speed preset, higher is faster. Default depends on the codec.
+    #[arg(long)]
+    pub preset: Option<u8>,
+
+    /// Pixel width of the preview. Will be scaled preserving aspect.
+    ///
+    /// Use this or -H (not both).
+    #[arg(long, short = 'W', conflicts_with = "height")]
+    pub width: Option<u32>,
+
+    /// Pixel height of the preview. Will be scaled preserving aspect.
+    ///
+    /// Use this or -W (not both). Default 270.
+    #[arg(long, short = 'H', conflicts_with = "width")]
+    pub height: Option<u32>,
+
+    #[clap(flatten)]
+    pub args: command::Extract,
+
+    /// Keep temporary files.
+    #[arg(long, default_value_t = false)]
+    pub keep: bool,
+}
+
+impl Preview {
+    pub fn run(mut self) -> anyhow::Result<()> {
+        let out_file = self
+            .output
+            .clone()
+            .unwrap_or_else(|| self.args.video.with_extension("webp"));
+        let encode = encode::Settings::new(
+            encode::Format::from_path(&out_file)?,
+            self.codec.as_deref(),
+            self.crf,
+            self.preset,
+        )?;
+
+        let temp_dir = temporary::process_dir(self.args.output_dir.clone(), !self.keep);
+        self.args.output_dir = Some(temp_dir.clone());
+        self.args.capture_frames = self.args.capture_frames.or(Some(30));
+        ensure!(
+            self.crossfade < self.args.capture_frames(),
+            "invalid crossfade must be less than capture-frames"
+        );
+
+        // default 270px high
+        self.args.vfilter = command::scale_vfilter(
+            self.args.vfilter.take(),
+            self.width,
+            self.height.or(self.width.is_none().then_some(270)),
+        );
+
+        let spinner = command::spinner(&temp_dir, self.keep)?;
+
+        spinner.set_message("Extracting");
+        let mut extract = self.args.run()?;
+        // skipped captures have no frames
+        let failed = std::mem::take(&mut extract.failed);
+        extract
+            .out_templates
+            .retain(|tmpl| !failed.iter().any(|(f, _)| f == tmpl));
+
+        for msg in &extract.warnings {
+            spinner.println(format!("Warning: {msg}"));
+        }
+
+        spinner.set_message("Concatenating");
+        let clips: Vec<_> = extract
+            .out_templates
+            .iter()
+            .map(|tmpl| Clip {
+                frames: (1..=self.args.capture_frames())
+                    .map(|f| temp_dir.join(tmpl.with_frame(f)))
+                    .collect(),
+                label: match self.label {
+                    true => label::seconds_text(tmpl.seconds),
+                    false => String::new(),
+                },
+            })
+            .collect();
+
+        let sequence = sequence(&clips, self.crossfade as usize);
+        let frame_w = sequence.len().to_string().len();
+        sequence.par_iter().enumerate().try_for_each(|(n, frame)| {
+            let out = temp_dir.join(format!("preview-{n:0frame_w$}.bmp"));
+            frame.write(&out)
+        })?;
+
+        let temp_out_file = temp_dir.join(out_file.file_name().context("invalid output")?);
+        spinner.set_message(format!("Encoding {}", sh_escape_filename(&out_file)));
+        encode::Encode {
+            input: encode::Input::Sequence {
+                pattern: &temp_dir.join(format!("preview-%0{frame_w}d.bmp")),
+                fps: self.fps,
+            },
+            settings: &encode,
+            output: &temp_out_file,
+        }
+        .run()?;
+
+        fs::rename(&temp_out_file, &out_file)
+            .or_else(|_| fs::copy(&temp_out_file, &out_file).map(|_| ()))?;
+
+        spinner.finish();
+        Ok(())
+    }
+}
+
+/// Frames of a single capture.
+struct Clip {
+    frames: Vec<PathBuf>,
+    label: String,
+}
+
+/// A single output frame.
+enum Frame<'a> {
+    Single(&'a Path, &'a str),
+    /// Blend of two frames, `alpha` being the opacity of the second.
+    Crossfade {
+        from: (&'a Path, &'a str),
+        to: (&'a Path, &'a str),
+        alpha: f32,
+    },
+}
+
+impl Frame<'_> {
+    fn write(&self, out: &Path) -> anyhow::Result<()> {
+        match *self {
+            Self::Single(path, "") => command::link_or_copy(path, out)?,
+            Self::Single(path, label) => load_labelled(path, label)?.save(out)?,
+            Self::Crossfade { from, to, alpha } => {
+                let mut from = load_labelled(from.0, from.1)?;
+                let to = load_labelled(to.0, to.1)?;
+                for (a, b) in from.pixels_mut().zip(to.pixels()) {
+                    for (a, b) in a.0.iter_mut().zip(b.0) {
+                        *a = (f32::from(*a) * (1.0 - alpha) + f32::from(b) * alpha).round() as u8;
+                    }
+                }
+                from.save(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn load_labelled(path: &Path, label: &str) -> anyhow::Result<image::RgbImage> {
+    let img = image::ImageReader::open(path)?.decode()?;
+    Ok(label::draw(img, label, &label::Config::default())?.into_rgb8())
+}
+
+/// Sequence clips one after another, overlapping the end of each clip with
+/// the start of the next by `crossfade` frames.
+fn sequence(clips: &[Clip], crossfade: usize) -> Vec<Frame<'_>> {
+    let mut frames = Vec::new();
+    for (idx, clip) in clips.iter().enumerate() {
+        let next = clips.get(idx + 1);
+        // frames at the start already blended into the previous clip's end
+        let skip = if idx == 0 { 0 } else { crossfade };
+        let fade_from = match next {
+            Some(_) => clip.frames.len().saturating_sub(crossfade),
+            None => clip.frames.len(),
+        };
+
+        for (f, path) in clip.frames.iter().enumerate().skip(skip) {
+            match next {
+                Some(next) if f >= fade_from => {
+                    let n = f - fade_from;
+                    frames.push(Frame::Crossfade {
+                        from: (path, &clip.label),
+                        to: (&next.frames[n.min(next.frames.len() - 1)], &next.label),
+                        alpha: (n + 1) as f32 / (crossfade + 1) as f32,
+                    });
+                }
+                _ => frames.push(Frame::Single(path, &clip.label)),
+            }
+        }
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(label: &str, frames: usize) -> Clip {
+        Clip {
+            frames: (0..frames).map(|f| format!("{label}{f}").into()).collect(),
+            label: label.into(),
+        }
+    }
+
+    fn names(frames: &[Frame]) -> Vec<String> {
+        frames
+            .iter()
+            .map(|frame| match frame {
+                Frame::Single(path, _) => path.display().to_string(),
+                Frame::Crossfade { from, to, alpha } => {
+                    format!("{}>{}@{alpha:.2}", from.0.display(), to.0.display())
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sequence_crossfades() {
+        let clips = [clip("a", 4), clip("b", 4), clip("c", 4)];
+        assert_eq!(
+            names(&sequence(&clips, 2)),
+            [
+                "a0",
+                "a1",
+                "a2>b0@0.33",
+                "a3>b1@0.67",
+                "b2>c0@0.33",
+                "b3>c1@0.67",
+                "c2",
+                "c3"
+            ]
+        );
+    }
+
+    #[test]
+    fn sequence_without_crossfade() {
+        let clips = [clip("a", 2), clip("b", 2)];
+        assert_eq!(names(&sequence(&clips, 0)), ["a0", "a1", "b0", "b1"]);
+    }
+
+    #[test]
+    fn sequence_crossfade_short_next_clip() {
+        // the next clip's last frame is held through the fade
+        let clips = [clip("a", 3), clip("b", 1)];
+        assert_eq!(
+            names(&sequence(&clips, 2)),
+            ["a0", "a1>b0@0.33", "a2>b0@0.67"]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{
+    command::{self, HumanDuration, sh_escape_filename},
+    temporary,
+};
+use anyhow::ensure;
+use std::{fmt::Write, fs, path::PathBuf};
+
+/// Create seek-preview thumbnail sprite sheets & a WebVTT file mapping
+/// time ranges to sprite regions, as used by web video players.
+#[derive(clap::Parser, Debug, Clone)]
+#[group(skip)]
+pub struct Sprites {
+    /// Time between each thumbnail.
+    #[arg(long, short, default_value = "10s")]
+    pub interval: HumanDuration,
+
+    /// Number of thumbnail columns in each sprite sheet.
+    #[arg(long, short, default_value_t = 10)]
+    pub columns: u32,
+
+    /// Maximum number of thumbnail rows in each sprite sheet.
+    /// Additional thumbnails are written to further sprite sheets.
+    #[arg(long, short, default_value_t = 10)]
+    pub rows: u32,
+
+    /// Pixel width of each thumbnail. Will be scaled preserving aspect.
+    ///
+    /// Use this or -H (not both). Default 160.
+    #[arg(long, short = 'W', conflicts_with = "thumb_height")]
+    pub thumb_width: Option<u32>,
+
+    /// Pixel height of each thumbnail. Will be scaled preserving aspect.
+    ///
+    /// Use this or -W (not both).
+    #[arg(long, short = 'H', conflicts_with = "thumb_width")]
+    pub thumb_height: Option<u32>,
+
+    /// Output vtt file name. Defaults to input with .vtt extension.
+    ///
+    /// Sprite sheets are written alongside as "NAME-1.jpg", "NAME-2.jpg", etc.
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Sprite sheet image format extension.
+    #[arg(long, default_value = "jpg")]
+    pub format: String,
+
+    /// Ffmpeg vfilter.
+    #[arg(long)]
+    pub vfilter: Option<String>,
+
+    /// Number of threads / concurrent ffmpeg calls. 0=auto.
+    #[arg(long, short = 'T', default_value_t = 3)]
+    pub threads: usize,
+
+    /// Keep temporary files.
+    #[arg(long, default_value_t = false)]
+    pub keep: bool,
+
+    /// Video file input.
+    #[arg(required = true)]
+    pub video: PathBuf,
+}
+
+impl Sprites {
+    pub fn run(self) -> anyhow::Result<()> {
+        ensure!(
+            self.interval.seconds > 0.0,
+            "invalid interval must be non-zero"
+        );
+        ensure!(
+            self.columns > 0 && self.rows > 0,
+            "invalid columns & rows must be non-zero"
+        );
+
+        let vtt_file = self
+            .output
+            .clone()
+            .unwrap_or_else(|| self.video.with_extension("vtt"));
+        let out_dir = match vtt_file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let sprite_prefix = vtt_file.with_extension("");
+        let sprite_prefix = sprite_prefix
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+
+        let temp_dir = temporary::process_dir(Some(out_dir.clone()), !self.keep);
+
+        let spinner = command::spinner(&temp_dir, self.keep)?;
+
+        spinner.set_message("Extracting");
+        let extract = command::Extract {
+            number: None,
+            interval: Some(self.interval),
+            ignore_start: <_>::default(),
+            ignore_end: <_>::default(),
+            capture_frames: Some(1),
+            capture_time: HumanDuration { seconds: 1.0 },
+            // default 160px wide
+            vfilter: command::scale_vfilter(
+                self.vfilter.clone(),
+                self.thumb_width
+                    .or(self.thumb_height.is_none().then_some(160)),
+                self.thumb_height,
+            ),
+            missing_frames: <_>::default(),
+            capture_timeout: command::CAPTURE_TIMEOUT,
+            capture_retries: command::CAPTURE_RETRIES,
+            skip_failed: false,
+            threads: self.threads,
+            output_dir: Some(temp_dir.clone()),
+            video: self.video.clone(),
+        }
+        .run()?;
+
+        for msg in &extract.warnings {
+            spinner.println(format!("Warning: {msg}"));
+        }
+
+        spinner.set_message("Joining");
+        let cue_s = self.interval.seconds;
+        let per_sheet = (self.columns * self.rows) as usize;
+        let mut vtt = String::from("WEBVTT\n");
+
+        for (sheet_idx, templates) in extract.out_templates.chunks(per_sheet).enumerate() {
+            let sheet_name = format!("{sprite_prefix}-{}.{}", sheet_idx + 1, self.format);
+            let join = command::Join {
+                columns: self.columns,
+                output: out_dir.join(&sheet_name),
+                capture_images: templates
+                    .iter()
+                    .map(|tmpl| temp_dir.join(tmpl.with_frame(1)))
+                    .collect(),
+                ..<_>::default()
+            }
+            .run()?;
+
+            for (n, cell) in join.cells.iter().enumerate() {
+                let idx = sheet_idx * per_sheet + n;
+                let start = cue_s * idx as f32;
+                let end = (cue_s * (idx + 1) as f32).min(extract.video_duration_s);
+                writeln!(
+                    vtt,
+                    "\n{} --> {}\n{sheet_name}#xywh={},{},{},{}",
+                    vtt_timestamp(start),
+                    vtt_timestamp(end),
+                    cell.x,
+                    cell.y,
+                    cell.width,
+                    cell.height,
+                )?;
+            }
+        }
+
+        spinner.set_message(format!("Writing {}", sh_escape_filename(&vtt_file)));
+        fs::write(&vtt_file, vtt)?;
+
+        spinner.finish();
+        Ok(())
+    }
+}
+
+/// "hh:mm:ss.ttt" WebVTT timestamp.
+fn vtt_timestamp(seconds: f32) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    let (hours, mins) = (millis / 3_600_000, (millis / 60_000) % 60);
+    let (secs, millis) = ((millis / 1000) % 60, millis % 1000);
+    format!("{hours:02}:{mins:02}:{secs:02}.{millis:03}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vtt_timestamps() {
+        assert_eq!(vtt_timestamp(0.0), "00:00:00.000");
+        assert_eq!(vtt_timestamp(61.25), "00:01:01.250");
+        assert_eq!(vtt_timestamp(3725.0), "01:02:05.000");
+        // rounds to the nearest millisecond, carrying into seconds
+        assert_eq!(vtt_timestamp(59.9996), "00:01:00.000");
+    }
+}
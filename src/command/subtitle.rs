@@ -0,0 +1,152 @@
+//! Reading subtitle cues from files or embedded streams.
+use crate::process::CommandExt;
+use anyhow::{Context, ensure};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+/// Subtitle file or embedded stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Index of a subtitle stream in the video.
+    Stream(usize),
+    /// External subtitle file, any format ffmpeg can read e.g. srt, ass, vtt.
+    File(PathBuf),
+}
+
+impl FromStr for Source {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().parse() {
+            Ok(idx) => Ok(Self::Stream(idx)),
+            Err(_) => Ok(Self::File(s.into())),
+        }
+    }
+}
+
+/// Text displayed between two times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start_s: f32,
+    pub end_s: f32,
+    pub text: String,
+}
+
+/// Read all cues of `source` by converting to srt with ffmpeg.
+pub fn load(video: &Path, source: &Source) -> anyhow::Result<Vec<Cue>> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.arg2("-v", "error");
+    match source {
+        Source::Stream(idx) => cmd.arg2("-i", video).arg2("-map", format!("0:s:{idx}")),
+        Source::File(file) => cmd.arg2("-i", file),
+    };
+    let out = cmd
+        .arg2("-f", "srt")
+        .arg("-")
+        .output()
+        .context("failed to run ffmpeg, is it installed & in $PATH?")?;
+    ensure!(
+        out.status.success(),
+        "ffmpeg subtitle conversion failed\n---stderr---\n{}\n------",
+        String::from_utf8_lossy(&out.stderr).trim(),
+    );
+    Ok(parse_srt(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// Text of the cue displayed at `seconds`, empty if none.
+pub fn text_at(cues: &[Cue], seconds: f32) -> &str {
+    cues.iter()
+        .find(|c| (c.start_s..c.end_s).contains(&seconds))
+        .map_or("", |c| &c.text)
+}
+
+/// Parse srt cues, ignoring formatting tags.
+///
+/// ```text
+/// 1
+/// 00:00:01,600 --> 00:00:04,200
+/// Subtitle text
+/// ```
+fn parse_srt(srt: &str) -> Vec<Cue> {
+    let srt = srt.replace("\r\n", "\n");
+    srt.split("\n\n")
+        .filter_map(|block| {
+            let mut lines = block.lines().skip_while(|l| !l.contains("-->"));
+            let (start, end) = lines.next()?.split_once("-->")?;
+            let text: Vec<_> = lines.map(strip_tags).filter(|l| !l.is_empty()).collect();
+            Some(Cue {
+                start_s: srt_seconds(start)?,
+                end_s: srt_seconds(end.split_whitespace().next()?)?,
+                text: text.join("\n"),
+            })
+        })
+        .collect()
+}
+
+/// "hh:mm:ss,ttt" -> seconds
+fn srt_seconds(timestamp: &str) -> Option<f32> {
+    let (hms, millis) = timestamp.trim().split_once(',')?;
+    let mut seconds = 0.0;
+    for part in hms.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f32>().ok()?;
+    }
+    Some(seconds + millis.parse::<f32>().ok()? / 1000.0)
+}
+
+/// Remove html style `<i>` & ass style `{\an8}` tags.
+fn strip_tags(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut close = None;
+    for c in line.chars() {
+        match (close, c) {
+            (None, '<') => close = Some('>'),
+            (None, '{') => close = Some('}'),
+            (None, c) => out.push(c),
+            (Some(end), c) if c == end => close = None,
+            _ => {}
+        }
+    }
+    out.trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_timestamps() {
+        assert_eq!(srt_seconds("00:00:01,600"), Some(1.6));
+        assert_eq!(srt_seconds(" 01:02:03,004 "), Some(3723.004));
+        assert_eq!(srt_seconds("00:00:01.600"), None);
+        assert_eq!(srt_seconds("00:xx:01,600"), None);
+    }
+
+    #[test]
+    fn srt_cues() {
+        let srt = "1\r\n00:00:01,600 --> 00:00:04,200\r\n<i>Hello</i>\r\n{\\an8}there\r\n\r\n\
+                   2\n00:00:05,000 --> 00:00:06,000 X1:0\nBye\n\n\
+                   3\nnot a cue\n";
+        let cues = parse_srt(srt);
+        assert_eq!(
+            cues,
+            [
+                Cue {
+                    start_s: 1.6,
+                    end_s: 4.2,
+                    text: "Hello\nthere".into(),
+                },
+                Cue {
+                    start_s: 5.0,
+                    end_s: 6.0,
+                    text: "Bye".into(),
+                },
+            ]
+        );
+        assert_eq!(text_at(&cues, 2.0), "Hello\nthere");
+        assert_eq!(text_at(&cues, 4.5), "");
+        assert_eq!(text_at(&cues, 6.0), "");
+    }
+}
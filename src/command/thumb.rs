@@ -0,0 +1,174 @@
+use crate::{
+    command::{self, DurationOrPercent, HumanDuration, label, metric, sh_escape},
+    temporary,
+};
+use anyhow::ensure;
+use rayon::prelude::*;
+use std::path::PathBuf;
+
+/// Pick the best looking frame of a video & write it as a thumbnail image.
+///
+/// Candidate frames are scored on sharpness, exposure, colourfulness
+/// & skin tones (a rough indication of faces).
+#[derive(clap::Parser, Debug, Clone)]
+#[group(skip)]
+pub struct Thumb {
+    /// Number of equidistant candidate frames to sample & score.
+    #[arg(long, default_value_t = 20)]
+    pub candidates: u32,
+
+    /// Write the top K ranked thumbnails "NAME-1.jpg", "NAME-2.jpg", etc.
+    #[arg(long, default_value_t = 1)]
+    pub top: usize,
+
+    /// Pixel width of the thumbnail. Will be scaled preserving aspect.
+    ///
+    /// Use this or -H (not both). Defaults to the video size.
+    #[arg(long, short = 'W', conflicts_with = "height")]
+    pub width: Option<u32>,
+
+    /// Pixel height of the thumbnail. Will be scaled preserving aspect.
+    ///
+    /// Use this or -W (not both). Defaults to the video size.
+    #[arg(long, short = 'H', conflicts_with = "width")]
+    pub height: Option<u32>,
+
+    /// Output file name, the extension determines the image format.
+    /// Defaults to input with .jpg extension.
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+
+    /// Time or percentage at the start to ignore when sampling candidates.
+    #[arg(long = "ignore-start", default_value = "5%")]
+    pub ignore_start: DurationOrPercent,
+
+    /// Time or percentage at the end to ignore when sampling candidates.
+    #[arg(long = "ignore-end", default_value = "10%")]
+    pub ignore_end: DurationOrPercent,
+
+    /// Ffmpeg vfilter.
+    #[arg(long)]
+    pub vfilter: Option<String>,
+
+    /// Number of threads / concurrent ffmpeg calls. 0=auto.
+    #[arg(long, short = 'T', default_value_t = 3)]
+    pub threads: usize,
+
+    /// Keep temporary files.
+    #[arg(long, default_value_t = false)]
+    pub keep: bool,
+
+    /// Video file input.
+    #[arg(required = true)]
+    pub video: PathBuf,
+}
+
+impl Thumb {
+    pub fn run(self) -> anyhow::Result<()> {
+        ensure!(self.candidates > 0, "invalid candidates must be non-zero");
+        ensure!(self.top > 0, "invalid top must be non-zero");
+
+        let out_file = self
+            .output
+            .clone()
+            .unwrap_or_else(|| self.video.with_extension("jpg"));
+        let temp_dir =
+            temporary::process_dir(out_file.parent().map(|p| p.to_path_buf()), !self.keep);
+
+        let spinner = command::spinner(&temp_dir, self.keep)?;
+
+        spinner.set_message("Extracting");
+        let extract = command::Extract {
+            number: Some(self.candidates),
+            interval: None,
+            ignore_start: self.ignore_start,
+            ignore_end: self.ignore_end,
+            capture_frames: Some(1),
+            capture_time: HumanDuration { seconds: 1.0 },
+            vfilter: command::scale_vfilter(self.vfilter.clone(), self.width, self.height),
+            missing_frames: <_>::default(),
+            capture_timeout: command::CAPTURE_TIMEOUT,
+            capture_retries: command::CAPTURE_RETRIES,
+            skip_failed: false,
+            threads: self.threads,
+            output_dir: Some(temp_dir.clone()),
+            video: self.video.clone(),
+        }
+        .run()?;
+
+        for msg in &extract.warnings {
+            spinner.println(format!("Warning: {msg}"));
+        }
+
+        spinner.set_message("Scoring");
+        let candidates: Vec<_> = extract
+            .out_templates
+            .iter()
+            .map(|tmpl| (tmpl, temp_dir.join(tmpl.with_frame(1))))
+            .collect();
+        let scores = candidates
+            .par_iter()
+            .map(|(_, path)| Ok(Score::measure(&metric::load_rgb(path)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // normalise unbounded sharpness relative to the sharpest candidate
+        let max_sharpness = scores
+            .iter()
+            .map(|s| s.sharpness)
+            .fold(f32::EPSILON, f32::max);
+        let mut ranked: Vec<_> = candidates
+            .iter()
+            .zip(&scores)
+            .map(|((tmpl, path), score)| (score.total(max_sharpness), *tmpl, path))
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        for (rank, (score, tmpl, path)) in ranked.into_iter().take(self.top).enumerate() {
+            let out = match self.top {
+                1 => out_file.clone(),
+                _ => command::numbered(&out_file, rank + 1),
+            };
+            image::ImageReader::open(path)?
+                .decode()?
+                .into_rgb8()
+                .save(&out)?;
+            spinner.println(format!(
+                "{} {} score {score:.3}",
+                sh_escape(&out),
+                label::seconds_text(tmpl.seconds),
+            ));
+        }
+
+        spinner.finish_and_clear();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Score {
+    sharpness: f32,
+    exposure: f32,
+    colourfulness: f32,
+    skin: f32,
+}
+
+impl Score {
+    fn measure(rgb: &image::RgbImage) -> Self {
+        let luma = image::DynamicImage::from(rgb.clone()).into_luma8();
+        Self {
+            sharpness: metric::sharpness(&luma),
+            exposure: metric::exposure(&luma),
+            colourfulness: metric::colourfulness(rgb),
+            skin: metric::skin(rgb),
+        }
+    }
+
+    /// Weighted score, higher is better.
+    fn total(&self, max_sharpness: f32) -> f32 {
+        0.35 * self.sharpness / max_sharpness
+            + 0.35 * self.exposure
+            + 0.2 * self.colourfulness.min(1.0)
+            // some skin is good, mostly skin less so
+            + 0.1 * (self.skin * 4.0).min(1.0)
+    }
+}
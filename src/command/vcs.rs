@@ -1,16 +1,29 @@
+mod html;
+mod metadata;
+mod timeline;
+mod waveform;
+
 use crate::{
-    command::{self, label, sh_escape, sh_escape_filename},
-    process::CommandExt,
+    command::{
+        self, ExtractData, HumanDuration, OutTemplate, encode, label, metric, sh_escape_filename,
+        subtitle,
+    },
     temporary,
 };
-use anyhow::ensure;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
-use std::{fs, path::PathBuf, process::Command, time::Duration};
+use anyhow::{Context, ensure};
+use rayon::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 /// Create a new contact sheet for a video.
 ///
 /// Extracts capture frames and joins into sheet(s) then encodes into
-/// an animated, or static, vcs avif.
+/// an animated, or static, vcs avif (or webp, mp4, webm).
+///
+/// Alternatively outputs an interactive html page when the output has a .html extension.
 #[derive(clap::Parser, Debug, Clone)]
 #[group(skip)]
 pub struct Vcs {
@@ -19,24 +32,41 @@ pub struct Vcs {
     pub columns: u32,
 
     /// Output file name. Defaults to input with .avif extension.
+    ///
+    /// The extension determines the format: avif, webp, mp4 (h.264) or webm (vp9 or av1).
+    ///
+    /// Use a .html extension to output a self-contained html page with hover-to-play
+    /// captures, each linking to its timestamp in the video.
     #[arg(long, short)]
     pub output: Option<PathBuf>,
 
-    /// Crf quality level for encoding the output avif.
-    #[arg(long, default_value_t = 30)]
-    pub avif_crf: u8,
+    /// Write html output frames into a "NAME_files" directory next to the page,
+    /// instead of embedding them, for a smaller & faster to open page.
+    #[arg(long, default_value_t = false)]
+    pub html_files: bool,
+
+    /// Crf quality level for encoding the output, lower is better quality.
+    /// Mapped to "-qp" for librav1e & "-quality" (100 - crf) for libwebp.
+    ///
+    /// Default 30 for av1 codecs, 23 for libx264, 32 for libvpx-vp9.
+    #[arg(long, visible_alias = "crf")]
+    pub avif_crf: Option<u8>,
 
-    /// Ffmpeg vcodec to use for encoding the output avif.
-    #[arg(long, default_value = "libsvtav1")]
-    pub avif_codec: String,
+    /// Ffmpeg vcodec to use for encoding the output.
+    ///
+    /// Default libsvtav1 for avif, libx264 for mp4, libvpx-vp9 for webm, libwebp for webp.
+    /// Webm may also use libsvtav1 or libaom-av1.
+    #[arg(long, visible_alias = "codec")]
+    pub avif_codec: Option<String>,
 
-    /// Preset (or "cpu-used" for libaom-av1) for encoding the output avif.
+    /// Encoder speed preset, higher is faster. Mapped to the codec's speed option,
+    /// e.g. "-preset" for libsvtav1, "-cpu-used" for libaom-av1, "-speed" for librav1e.
     ///
-    /// Default 1 for single-frame, 6 for multi-frame.
-    #[arg(long)]
+    /// Default 1 for single-frame avif, otherwise depends on the codec.
+    #[arg(long, visible_alias = "preset")]
     pub avif_preset: Option<u8>,
 
-    /// Output avif framerate for multi-frame outputs.
+    /// Output framerate for multi-frame outputs.
     ///
     /// Example: The default 20fps will result in real time playback for
     /// the default args: -f30 -t1500ms (30 frames over a 1.5s duration).
@@ -44,6 +74,29 @@ pub struct Vcs {
     #[arg(long, default_value_t = 20.0)]
     pub avif_fps: f32,
 
+    /// Play multi-frame outputs forwards then backwards, avoiding a jump from
+    /// the last frame back to the first when looping.
+    #[arg(long, default_value_t = false)]
+    pub ping_pong: bool,
+
+    /// Duration to hold the first frame of multi-frame outputs, e.g. "1s".
+    #[arg(long)]
+    pub hold: Option<HumanDuration>,
+
+    /// Number of times multi-frame avif & webp outputs play. Default 0, loop forever.
+    #[arg(long = "loop")]
+    pub loops: Option<u32>,
+
+    /// Merge consecutive identical frames of multi-frame outputs, e.g. static slides
+    /// or paused video, into single longer frames using variable frame timing.
+    /// Reduces encode time & output size.
+    #[arg(long, default_value_t = false)]
+    pub dedupe: bool,
+
+    /// Maximum per-channel difference, 0-255, for --dedupe to consider frames identical.
+    #[arg(long, default_value_t = 0, requires = "dedupe")]
+    pub dedupe_tolerance: u8,
+
     /// Pixel width of each capture inside the grid. Will be scaled preserving aspect.
     ///
     /// Use this or -H (not both).
@@ -56,9 +109,87 @@ pub struct Vcs {
     #[arg(long, short = 'H', conflicts_with = "capture_width", required = true)]
     pub capture_height: Option<u32>,
 
+    /// Target maximum output file size, e.g. "2MB", "500KiB". The crf will be
+    /// estimated using trial encodes of a subset of frames, then refined
+    /// with full encodes, to fit within this size.
+    #[arg(long)]
+    pub max_size: Option<command::ByteSize>,
+
+    /// Maximum number of capture rows in a single output sheet.
+    /// Additional captures are written to further sheets "NAME-1.avif", "NAME-2.avif", etc.
+    ///
+    #[arg(long, conflicts_with = "per_page")]
+    pub max_rows: Option<u32>,
+
+    /// Maximum number of captures in a single output sheet.
+    /// Additional captures are written to further sheets "NAME-1.avif", "NAME-2.avif", etc.
+    ///
+    #[arg(long, conflicts_with = "max_rows")]
+    pub per_page: Option<u32>,
+
+    /// Render one capture larger at the top-left of the grid, so the sheet doubles as a poster.
+    ///
+    /// "first": the first capture.
+    /// "scene": the capture most different to its neighbours.
+    /// TIME, e.g. "5m": the capture nearest to the given time.
+    #[arg(long)]
+    pub hero: Option<Hero>,
+
+    /// Number of columns & rows the --hero capture spans.
+    #[arg(long, default_value_t = 2, requires = "hero")]
+    pub hero_span: u32,
+
+    /// Animate only some captures, the rest are static single frames.
+    /// Reduces extraction time & output size.
+    ///
+    /// INDEX,..., e.g. "0,4,7": the captures at the given 0-based indices.
+    /// "top:K": the K captures with the most motion.
+    #[arg(long, conflicts_with = "adaptive_frames")]
+    pub animate: Option<Animate>,
+
+    /// Measure motion in each capture & spend frames where there is action.
+    /// The most active capture uses all capture-frames, others use fewer
+    /// frames in proportion to their motion, repeated to keep the sheet in sync.
+    #[arg(long, default_value_t = false)]
+    pub adaptive_frames: bool,
+
+    /// Draw an audio loudness strip beneath the grid with markers at each capture's time.
+    #[arg(long, default_value_t = false)]
+    pub waveform: bool,
+
+    /// Draw a timeline bar across the bottom of the sheet showing the capture points,
+    /// ignored regions & a playhead for each capture on animated sheets.
+    #[arg(long, default_value_t = false)]
+    pub timeline: bool,
+
+    /// Draw a thin progress bar along the bottom of each capture showing
+    /// its position within the video.
+    #[arg(long, default_value_t = false)]
+    pub progress_bar: bool,
+
+    /// Draw the subtitle displayed at each capture's time.
+    ///
+    /// A subtitle file (srt, ass, vtt) or the index of a subtitle stream
+    /// in the video, e.g. "0" for the first.
+    #[arg(long)]
+    pub subtitles: Option<subtitle::Source>,
+
+    #[clap(flatten)]
+    pub watermark: command::watermark::Watermark,
+
+    /// Font file used to draw text, before the embedded & system fallback fonts.
+    /// May be specified multiple times to form a fallback chain.
+    #[arg(long)]
+    pub font: Vec<PathBuf>,
+
     #[clap(flatten)]
     pub args: command::Extract,
 
+    /// Write a sidecar json file, next to the output, describing the source video
+    /// and the timestamp & position of each capture in the grid.
+    #[arg(long, default_value_t = false)]
+    pub metadata: bool,
+
     /// Keep temporary files.
     #[arg(long, default_value_t = false)]
     pub keep: bool,
@@ -66,148 +197,911 @@ pub struct Vcs {
 
 impl Vcs {
     pub fn run(mut self) -> anyhow::Result<()> {
-        ensure!(
-            self.output
-                .as_ref()
-                .is_none_or(|p| p.extension().and_then(|e| e.to_str()) == Some("avif")),
-            "output must be avif"
-        );
+        let file_prefix = self.args.video.with_extension("");
+        let file_prefix = file_prefix
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .replace('%', "");
 
-        let parent_dir = self
-            .args
-            .output_dir
-            .clone()
-            .unwrap_or_else(|| PathBuf::from("."));
+        // output file if successful
+        let out_file = self.output.clone().unwrap_or_else(|| {
+            let mut o = self
+                .args
+                .output_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("."));
+            o.push(format!("{file_prefix}.avif"));
+            o
+        });
+        let html = out_file.extension().is_some_and(|e| e == "html");
+        let encode = match html {
+            true => None,
+            false => Some(self.encode_settings(&out_file)?),
+        };
+        if html {
+            let unsupported = [
+                (self.metadata, "--metadata"),
+                (self.hero.is_some(), "--hero"),
+                (self.per_page.is_some(), "--per-page"),
+                (self.max_rows.is_some(), "--max-rows"),
+                (self.timeline, "--timeline"),
+                (self.waveform, "--waveform"),
+                (self.subtitles.is_some(), "--subtitles"),
+                (self.watermark.watermark.is_some(), "--watermark"),
+                (self.watermark.watermark_text.is_some(), "--watermark-text"),
+                (self.progress_bar, "--progress-bar"),
+                (self.max_size.is_some(), "--max-size"),
+                (self.dedupe, "--dedupe"),
+                (self.ping_pong, "--ping-pong"),
+                (self.hold.is_some(), "--hold"),
+                (self.loops.is_some(), "--loop"),
+            ];
+            for (set, flag) in unsupported {
+                ensure!(!set, "{flag} is not supported for html output");
+            }
+        }
+        label::init_fonts(&self.font)?;
         let temp_dir = temporary::process_dir(self.args.output_dir.clone(), !self.keep);
 
         self.args.output_dir = Some(temp_dir.clone());
         self.args.capture_frames = self.args.capture_frames.or(Some(30));
 
-        let ex_scale = self.extract_scale();
-        self.args.vfilter = match (self.args.vfilter, ex_scale) {
-            (Some(vf), Some(scale)) => Some(format!("{vf},{scale}")),
-            (vf, scale) => vf.or(scale),
-        };
-
-        let spinner = indicatif::ProgressBar::new_spinner().with_style(
-            indicatif::ProgressStyle::default_spinner()
-                .template("{spinner:.cyan.bold} {elapsed_precise:.bold} {msg}")?,
+        self.args.vfilter = command::scale_vfilter(
+            self.args.vfilter.take(),
+            self.capture_width,
+            self.capture_height,
         );
-        spinner.enable_steady_tick(Duration::from_millis(100));
 
-        if self.keep {
-            spinner.println(format!(
-                "Keeping temporary files in {}",
-                sh_escape(&temp_dir)
-            ));
-        }
+        let spinner = command::spinner(&temp_dir, self.keep)?;
 
-        spinner.set_message("Extracting");
-        let extract = self.args.run()?;
+        let extract = match &self.animate {
+            None if self.adaptive_frames => {
+                let capture_frames = self.args.capture_frames();
+                let motion = measure_motion(&self.args, &temp_dir, &spinner)?;
+                let max_motion = motion.iter().copied().fold(f32::EPSILON, f32::max);
+                spinner.set_message("Extracting");
+                self.args.run_frames(|n| {
+                    let motion = motion.get(n).copied().unwrap_or(max_motion);
+                    ((capture_frames as f32 * motion / max_motion).ceil() as u32)
+                        .clamp(1, capture_frames)
+                })?
+            }
+            None => {
+                spinner.set_message("Extracting");
+                self.args.run()?
+            }
+            Some(animate) => {
+                let animated = animate.select(&self.args, &temp_dir, &spinner)?;
+                spinner.set_message("Extracting");
+                let capture_frames = self.args.capture_frames();
+                self.args.run_frames(|n| match animated.contains(&n) {
+                    true => capture_frames,
+                    false => 1,
+                })?
+            }
+        };
 
         for msg in &extract.warnings {
             spinner.println(format!("Warning: {msg}"));
         }
+        write_placeholders(&extract, &temp_dir)?;
+
+        if html {
+            spinner.set_message(format!("Writing {}", sh_escape_filename(&out_file)));
+            html::Page {
+                video: &self.args.video,
+                columns: self.columns,
+                fps: self.avif_fps,
+                capture_frames: self.args.capture_frames(),
+                jpeg_quality: 85,
+                embed: !self.html_files,
+            }
+            .write(&extract, &temp_dir, &out_file)?;
+            spinner.finish();
+            return Ok(());
+        }
+
+        let per_page = match (self.per_page, self.max_rows) {
+            (Some(n), _) => n.max(1) as usize,
+            (None, Some(rows)) => (self.columns.max(1) * rows.max(1)) as usize,
+            (None, None) => extract.out_templates.len().max(1),
+        };
+        let hero = match self.hero {
+            Some(hero) => Some(hero.select(&extract.out_templates, &temp_dir)?),
+            None => None,
+        };
+        let pages: Vec<_> = extract.out_templates.chunks(per_page).collect();
+        let cues = match &self.subtitles {
+            Some(source) => {
+                spinner.set_message("Reading subtitles");
+                subtitle::load(&self.args.video, source)?
+            }
+            None => vec![],
+        };
+        let loudness = match self.waveform {
+            true => {
+                spinner.set_message("Decoding audio");
+                let first = &extract.out_templates[0];
+                let (cap_w, _) = image::image_dimensions(temp_dir.join(first.with_frame(1)))?;
+                let (cols, _) =
+                    command::grid_size(pages[0].len(), self.columns, hero, self.hero_span);
+                Some(waveform::loudness(
+                    &self.args.video,
+                    cap_w * cols,
+                    extract.video_duration_s,
+                )?)
+            }
+            false => None,
+        };
+        for (idx, templates) in pages.iter().enumerate() {
+            let hero = hero
+                .and_then(|h| h.checked_sub(idx * per_page))
+                .filter(|h| *h < templates.len());
+            let page = match pages.len() {
+                1 => Page {
+                    templates,
+                    prefix: file_prefix.clone(),
+                    out_file: out_file.clone(),
+                    header: None,
+                    hero,
+                    loudness: loudness.as_deref(),
+                    cues: &cues,
+                },
+                n => Page {
+                    templates,
+                    prefix: format!("{file_prefix}-{}", idx + 1),
+                    out_file: command::numbered(&out_file, idx + 1),
+                    header: Some(self.page_header(templates, idx + 1, n)),
+                    hero,
+                    loudness: loudness.as_deref(),
+                    cues: &cues,
+                },
+            };
+            self.write_page(&page, encode.as_ref(), &extract, &temp_dir, &spinner)?;
+        }
+
+        spinner.finish();
+        Ok(())
+    }
 
-        spinner.set_message("Joining");
+    /// Join & encode a single output sheet.
+    fn write_page(
+        &self,
+        page: &Page<'_>,
+        encode: Option<&encode::Settings>,
+        extract: &ExtractData,
+        temp_dir: &Path,
+        spinner: &indicatif::ProgressBar,
+    ) -> anyhow::Result<()> {
+        let Page {
+            templates,
+            prefix,
+            out_file,
+            header,
+            hero,
+            loudness,
+            cues,
+        } = page;
+
+        spinner.set_message(format!("Joining {}", sh_escape_filename(out_file)));
         let frame_w = self.args.capture_frames().to_string().len();
-        let file_prefix = self.args.video.with_extension("");
-        let file_prefix = file_prefix
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .replace('%', "");
 
-        (0..self.args.capture_frames())
-            .into_par_iter()
-            .try_for_each(|f| {
-                let capture_images: Vec<_> = extract
-                    .out_templates
+        let (cap_w, cap_h) = image::image_dimensions(temp_dir.join(templates[0].with_frame(1)))?;
+        let mut footer = vec![];
+        if let Some(loudness) = loudness {
+            let markers: Vec<_> = templates
+                .iter()
+                .map(|tmpl| {
+                    (tmpl.seconds as f32 + self.args.capture_time.seconds * 0.5)
+                        / extract.video_duration_s
+                })
+                .collect();
+            let path = temp_dir.join(format!("{prefix}-waveform.bmp"));
+            waveform::render(loudness, (cap_h / 3).max(16), &markers).save(&path)?;
+            footer.push(path);
+        }
+
+        let timeline = self.timeline.then(|| timeline::Timeline {
+            duration_s: extract.video_duration_s,
+            ignore_start_s: self.args.ignore_start.to_secs(extract.video_duration_s),
+            ignore_end_s: self.args.ignore_end.to_secs(extract.video_duration_s),
+            captures: templates
+                .iter()
+                .map(|t| (t.seconds as f32, t.frames > 1))
+                .collect(),
+            capture_time_s: self.args.capture_time.seconds,
+        });
+        let (cols, _) = command::grid_size(templates.len(), self.columns, *hero, self.hero_span);
+
+        let frame_timeline = |f: u32| -> Vec<image::RgbImage> {
+            let progress = match self.args.capture_frames() {
+                1 => None,
+                frames => Some(f as f32 / (frames - 1) as f32),
+            };
+            timeline
+                .iter()
+                .map(|t| t.render(cap_w * cols, (cap_h / 8).max(8), progress))
+                .collect()
+        };
+
+        let frame_join = |f: u32| -> anyhow::Result<command::Join> {
+            let capture_images: Vec<_> = templates
+                .iter()
+                .map(|tmpl| {
+                    temp_dir.join(tmpl.with_frame(tmpl.frame_at(f + 1, self.args.capture_frames())))
+                })
+                .collect();
+
+            let label = templates
+                .iter()
+                .map(|tmpl| label::seconds_text(tmpl.seconds))
+                .collect();
+
+            let seconds =
+                self.args.capture_time.seconds * f as f32 / self.args.capture_frames() as f32;
+            let caption = match cues.is_empty() {
+                true => vec![],
+                false => templates
                     .iter()
-                    .map(|tmpl| {
-                        let mut o = temp_dir.to_path_buf();
-                        o.push(tmpl.with_frame(f + 1));
-                        o
-                    })
-                    .collect();
-
-                let label = extract
-                    .out_templates
+                    .map(|tmpl| subtitle::text_at(cues, tmpl.seconds as f32 + seconds).to_owned())
+                    .collect(),
+            };
+
+            let progress = match self.progress_bar {
+                true => templates
                     .iter()
-                    .map(|tmpl| label::seconds_text(tmpl.seconds))
-                    .collect();
-
-                command::Join {
-                    columns: self.columns,
-                    output: {
-                        let mut o = temp_dir.to_path_buf();
-                        o.push(format!("{file_prefix}-{f:0frame_w$}.bmp"));
-                        o
-                    },
-                    capture_images,
-                    capture_width: None,
-                    capture_height: None,
-                    label,
-                }
-                .run()
-            })?;
+                    .map(|tmpl| tmpl.seconds as f32 / extract.video_duration_s)
+                    .collect(),
+                false => vec![],
+            };
 
+            Ok(command::Join {
+                columns: self.columns,
+                output: temp_dir.join(format!("{prefix}-{f:0frame_w$}.bmp")),
+                capture_images,
+                label,
+                caption,
+                progress,
+                hero: *hero,
+                hero_span: self.hero_span,
+                header: header.clone(),
+                footer: footer.clone(),
+                watermark: self.watermark.clone(),
+                font: self.font.clone(),
+                ..<_>::default()
+            })
+        };
+
+        // compose consecutive frames on each thread, so static parts are only drawn once
+        let frames: Vec<_> = (0..self.args.capture_frames()).collect();
+        let chunk_len = frames.len().div_ceil(rayon::current_num_threads()).max(1);
+        let join = frames
+            .par_chunks(chunk_len)
+            .map(|frames| {
+                let mut compositor = command::Compositor::default();
+                frames
+                    .iter()
+                    .map(|f| compositor.compose_with_footers(&frame_join(*f)?, &frame_timeline(*f)))
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .swap_remove(0)
+            .swap_remove(0);
+
+        let encode = encode.context("missing encode settings")?;
         // write to temp location until successful
-        let temp_out_file = {
-            let mut o = temp_dir.clone();
-            o.push(format!("{file_prefix}.avif"));
-            o
+        let temp_out_file = temp_dir.join(out_file.file_name().context("invalid output")?);
+
+        // link joined frames into playback order
+        let joined = |f: u32| temp_dir.join(format!("{prefix}-{f:0frame_w$}.bmp"));
+        let order = self.playback((0..self.args.capture_frames()).collect());
+        let play_w = order.len().to_string().len();
+        let frames: Vec<_> = order
+            .iter()
+            .enumerate()
+            .map(|(n, f)| {
+                let play = temp_dir.join(format!("{prefix}-play-{n:0play_w$}.bmp"));
+                command::link_or_copy(&joined(*f), &play)?;
+                Ok(play)
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        // encode runs of matching frames as single longer frames
+        let runs = match self.dedupe {
+            true => {
+                spinner.set_message(format!("Deduping {}", sh_escape_filename(out_file)));
+                dedupe(&order, joined, self.dedupe_tolerance)?
+            }
+            false => vec![],
         };
-        // output file if successful
-        let out_file = self.output.unwrap_or_else(|| {
-            let mut o = parent_dir;
-            o.push(format!("{file_prefix}.avif"));
-            o
+        let variable = !runs.is_empty() && runs.len() < order.len();
+        let frames: Vec<_> = match variable {
+            true => runs
+                .into_iter()
+                .map(|(f, n)| (joined(f), n as f32 / self.avif_fps))
+                .collect(),
+            false => frames
+                .into_iter()
+                .map(|frame| (frame, 1.0 / self.avif_fps))
+                .collect(),
+        };
+        let play_pattern = temp_dir.join(format!("{prefix}-play-%0{play_w}d.bmp"));
+        let concat_list = temp_dir.join(format!("{prefix}-play.ffconcat"));
+        let input = match variable {
+            false => encode::Input::Sequence {
+                pattern: &play_pattern,
+                fps: self.avif_fps,
+            },
+            true => {
+                encode::write_concat(&concat_list, &frames)?;
+                encode::Input::Concat(&concat_list)
+            }
+        };
+
+        let mut encode = *encode;
+        if let Some(max_size) = self.max_size {
+            spinner.set_message(format!("Searching crf {}", sh_escape_filename(out_file)));
+            encode.crf = encode.max_size_crf(
+                &frames,
+                variable,
+                max_size.bytes,
+                &temp_dir.join(format!("{prefix}-trial")),
+            )?;
+        }
+
+        spinner.set_message(format!("Encoding {}", sh_escape_filename(out_file)));
+        let encode_crf = |crf: u8, output: &Path| -> anyhow::Result<u64> {
+            encode::Encode {
+                input,
+                settings: &encode::Settings { crf, ..encode },
+                output,
+            }
+            .run()?;
+            Ok(fs::metadata(output)?.len())
+        };
+        match self.max_size {
+            None => _ = encode_crf(encode.crf, &temp_out_file)?,
+            Some(max_size) => {
+                let attempt_out = |crf: u8| {
+                    temp_dir.join(format!("{prefix}-crf{crf}.{}", encode.format.extension()))
+                };
+                let best = encode.fit_crf(max_size.bytes, |crf| {
+                    spinner.set_message(format!(
+                        "Encoding {} crf {crf}",
+                        sh_escape_filename(out_file)
+                    ));
+                    encode_crf(crf, &attempt_out(crf))
+                })?;
+                let Some((crf, bytes)) = best else {
+                    anyhow::bail!(
+                        "unable to fit {} within {max_size} at crf {}",
+                        sh_escape_filename(out_file),
+                        encode.max_crf(),
+                    );
+                };
+                fs::rename(attempt_out(crf), &temp_out_file)?;
+                let size = command::ByteSize { bytes };
+                spinner.println(format!(
+                    "{}: crf {crf} {size}",
+                    sh_escape_filename(out_file)
+                ));
+            }
+        }
+
+        fs::rename(&temp_out_file, out_file)
+            .or_else(|_| fs::copy(&temp_out_file, out_file).map(|_| ()))?;
+
+        if self.metadata {
+            metadata::Metadata::new(
+                &self.args.video,
+                self.args.capture_time.seconds,
+                self.args.capture_frames(),
+                extract.video_duration_s,
+                templates,
+                &join,
+            )?
+            .write(&out_file.with_extension("json"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Validated encoder settings for the output.
+    fn encode_settings(&self, out_file: &Path) -> anyhow::Result<encode::Settings> {
+        let format = encode::Format::from_path(out_file)?;
+        ensure!(
+            self.loops.is_none() || format.supports_loop(),
+            "--loop is not supported for {format:?} output"
+        );
+        let static_avif = format == encode::Format::Avif && self.args.capture_frames() == 1;
+        let mut settings = encode::Settings::new(
+            format,
+            self.avif_codec.as_deref(),
+            self.avif_crf,
+            self.avif_preset.or(static_avif.then_some(1)),
+        )?;
+        settings.loops = self.loops.unwrap_or(0);
+        Ok(settings)
+    }
+
+    /// Order joined `frames` for playback applying --hold & --ping-pong.
+    fn playback(&self, mut frames: Vec<u32>) -> Vec<u32> {
+        if self.ping_pong && frames.len() > 2 {
+            let reverse: Vec<_> = frames[1..frames.len() - 1].iter().rev().copied().collect();
+            frames.extend(reverse);
+        }
+        if let (Some(hold), Some(first)) = (self.hold, frames.first().copied()) {
+            let repeats = (hold.seconds * self.avif_fps).round() as usize;
+            frames.splice(0..0, std::iter::repeat_n(first, repeats));
+        }
+        frames
+    }
+
+    /// Header text for a page of a multi-page output, e.g. "bbb.mkv  2/4  02:30 - 05:01".
+    fn page_header(&self, templates: &[OutTemplate], page: usize, pages: usize) -> String {
+        let name = self.args.video.file_name().unwrap_or_default();
+        let start = templates.first().map_or(0, |t| t.seconds);
+        let end = templates.last().map_or(0, |t| {
+            t.seconds + self.args.capture_time.seconds.ceil() as u32
         });
+        format!(
+            "{}  {page}/{pages}  {} - {}",
+            name.to_string_lossy(),
+            label::seconds_text(start),
+            label::seconds_text(end),
+        )
+    }
+}
+
+/// A single output sheet of captures.
+struct Page<'a> {
+    templates: &'a [OutTemplate],
+    /// Temporary file prefix.
+    prefix: String,
+    out_file: PathBuf,
+    header: Option<String>,
+    /// Page index of the hero capture.
+    hero: Option<usize>,
+    /// Audio loudness to draw beneath the grid.
+    loudness: Option<&'a [f32]>,
+    /// Subtitle cues to draw on captures.
+    cues: &'a [subtitle::Cue],
+}
+
+/// Group playback `order` of joined frames into runs of frames matching the run's
+/// first frame within `tolerance`, returning each run's first frame & length.
+fn dedupe(
+    order: &[u32],
+    joined: impl Fn(u32) -> PathBuf + Sync,
+    tolerance: u8,
+) -> anyhow::Result<Vec<(u32, usize)>> {
+    frame_runs(
+        order,
+        order.len().div_ceil(rayon::current_num_threads()),
+        |f| Ok(image::ImageReader::open(joined(f))?.decode()?.into_rgb8()),
+        |a, b| metric::within_tolerance(a, b, tolerance),
+    )
+}
+
+/// Group `order` into runs of frames where `same(first, frame)` for the run's first frame.
+///
+/// Chunks of `chunk_len` frames are grouped concurrently, only the first & last images
+/// of each are kept to join runs across chunks. A chunk continuing the previous run is
+/// regrouped against that run's first image until its runs line up again.
+fn frame_runs<T: Send>(
+    order: &[u32],
+    chunk_len: usize,
+    load: impl Fn(u32) -> anyhow::Result<T> + Sync,
+    same: impl Fn(&T, &T) -> bool + Sync,
+) -> anyhow::Result<Vec<(u32, usize)>> {
+    let chunk_len = chunk_len.max(1);
+    let chunks = order
+        .par_chunks(chunk_len)
+        .map(|chunk| {
+            let mut runs = vec![];
+            group_runs(&mut runs, chunk, &load, &same, |_| false)?;
+            Ok(runs)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut runs: Vec<(u32, usize, Option<T>)> = vec![];
+    for (frames, chunk) in order.chunks(chunk_len).zip(chunks) {
+        let continues = match (runs.last(), chunk.first()) {
+            (Some(last), Some(next)) => {
+                last.0 == next.0 || matches!((&last.2, &next.2), (Some(a), Some(b)) if same(a, b))
+            }
+            _ => false,
+        };
+        if !continues {
+            runs.extend(chunk);
+            continue;
+        }
 
-        spinner.set_message(format!("Encoding {}", sh_escape_filename(&out_file)));
-        let out = Command::new("ffmpeg")
-            .arg2("-r", self.avif_fps)
-            .arg2("-i", {
-                let mut o = temp_dir;
-                o.push(format!("{file_prefix}-%0{frame_w}d.bmp"));
-                o
+        // the chunk's frames were compared to their own run's first image, not the
+        // continued run's, so regroup until a run starts where one did in the chunk
+        let starts: Vec<_> = chunk
+            .iter()
+            .scan(0, |idx, (_, n, _)| {
+                let start = *idx;
+                *idx += n;
+                Some(start)
             })
-            .arg2("-c:v", &self.avif_codec)
-            .arg2(
-                match self.avif_codec.as_str() {
-                    "libaom-av1" => "-cpu-used",
-                    _ => "-preset",
-                },
-                self.avif_preset
-                    .unwrap_or(match self.args.capture_frames() {
-                        1 => 1,
-                        _ => 6,
-                    }),
-            )
-            .arg2("-crf", self.avif_crf)
-            .arg2("-pix_fmt", "yuv420p10le")
-            .arg("-y")
-            .arg(&temp_out_file)
-            .output()?;
-        ensure!(
-            out.status.success(),
-            "ffmpeg convert-to-avif failed\n---stderr---\n{}\n------",
-            String::from_utf8_lossy(&out.stderr).trim(),
+            .collect();
+        let grouped = group_runs(&mut runs, frames, &load, &same, |idx| {
+            starts.binary_search(&idx).is_ok()
+        })?;
+        runs.extend(
+            chunk
+                .into_iter()
+                .zip(starts)
+                .filter(|(_, start)| *start >= grouped)
+                .map(|(run, _)| run),
         );
+    }
+    Ok(runs.into_iter().map(|(f, n, _)| (f, n)).collect())
+}
 
-        fs::rename(&temp_out_file, &out_file)
-            .or_else(|_| fs::copy(&temp_out_file, &out_file).map(|_| ()))?;
+/// Group `frames` onto the end of `runs`, returning the number of frames grouped.
+///
+/// Stops before starting a new run at a frame index where `stop(idx)`. Only the
+/// first & last runs' images are kept.
+fn group_runs<T>(
+    runs: &mut Vec<(u32, usize, Option<T>)>,
+    frames: &[u32],
+    load: impl Fn(u32) -> anyhow::Result<T>,
+    same: impl Fn(&T, &T) -> bool,
+    stop: impl Fn(usize) -> bool,
+) -> anyhow::Result<usize> {
+    for (idx, &f) in frames.iter().enumerate() {
+        if let Some((first, n, _)) = runs.last_mut()
+            && *first == f
+        {
+            *n += 1;
+            continue;
+        }
+        let img = load(f)?;
+        match runs.last_mut() {
+            Some((_, n, Some(first_img))) if same(first_img, &img) => *n += 1,
+            _ => {
+                if stop(idx) {
+                    return Ok(idx);
+                }
+                if runs.len() > 1
+                    && let Some((_, _, img)) = runs.last_mut()
+                {
+                    *img = None;
+                }
+                runs.push((f, 1, Some(img)));
+            }
+        }
+    }
+    Ok(frames.len())
+}
 
-        spinner.finish();
-        Ok(())
+/// Write frames for captures skipped with --skip-failed, showing the error.
+fn write_placeholders(extract: &ExtractData, dir: &Path) -> anyhow::Result<()> {
+    if extract.failed.is_empty() {
+        return Ok(());
     }
+    let is_failed = |tmpl: &OutTemplate| extract.failed.iter().any(|(f, _)| f == tmpl);
+    let Some(captured) = extract.out_templates.iter().find(|t| !is_failed(t)) else {
+        anyhow::bail!("all captures failed");
+    };
+    let (w, h) = image::image_dimensions(dir.join(captured.with_frame(1)))?;
 
-    fn extract_scale(&self) -> Option<String> {
-        if let Some(h) = self.capture_height {
-            return Some(format!("scale=-1:{h}:flags=bicubic"));
+    for (tmpl, err) in &extract.failed {
+        let img = image::RgbaImage::from_pixel(w, h, image::Rgba([20, 20, 20, 255]));
+        let conf = label::Config {
+            v_align: glyph_brush_layout::VerticalAlign::Center,
+            background_opacity: 0.0,
+            ..label::Config::caption()
+        };
+        let first = dir.join(tmpl.with_frame(1));
+        label::draw(img.into(), err, &conf)?
+            .into_rgb8()
+            .save(&first)?;
+        for f in 2..=tmpl.frames {
+            let next = dir.join(tmpl.with_frame(f));
+            command::link_or_copy(&first, &next)?;
         }
-        let w = self.capture_width?;
-        Some(format!("scale={w}:-1:flags=bicubic"))
+    }
+    Ok(())
+}
+
+/// Number of frames extracted per capture to measure motion.
+const MOTION_PROBE_FRAMES: u32 = 3;
+
+/// Measure the motion of each capture as the sum of luma differences between
+/// a few probe frames extracted across the capture time.
+fn measure_motion(
+    args: &command::Extract,
+    temp_dir: &Path,
+    spinner: &indicatif::ProgressBar,
+) -> anyhow::Result<Vec<f32>> {
+    spinner.set_message("Measuring motion");
+    let probe_dir = temp_dir.join("motion-probe");
+    let probe = command::Extract {
+        capture_frames: Some(MOTION_PROBE_FRAMES),
+        output_dir: Some(probe_dir.clone()),
+        ..args.clone()
+    }
+    .run()?;
+
+    probe
+        .out_templates
+        .par_iter()
+        .map(|tmpl| {
+            if probe.failed.iter().any(|(failed, _)| failed == tmpl) {
+                return Ok(0.0);
+            }
+            let frames = (1..=tmpl.frames)
+                .map(|f| metric::load_luma(&probe_dir.join(tmpl.with_frame(f))))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Ok(frames
+                .windows(2)
+                .map(|w| metric::difference(&w[0], &w[1]))
+                .sum())
+        })
+        .collect()
+}
+
+/// Captures to animate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Animate {
+    Indices(Vec<usize>),
+    Top(usize),
+}
+
+impl Animate {
+    /// Return the indices of the captures to animate.
+    ///
+    /// For "top:K" this first probes each capture with a few frames to measure motion.
+    fn select(
+        &self,
+        args: &command::Extract,
+        temp_dir: &Path,
+        spinner: &indicatif::ProgressBar,
+    ) -> anyhow::Result<Vec<usize>> {
+        let k = match self {
+            Self::Indices(indices) => {
+                let duration_s = command::video_duration(&args.video)?;
+                let captures = args.capture_starts(duration_s)?.len();
+                if let Some(idx) = indices.iter().find(|i| **i >= captures) {
+                    anyhow::bail!("invalid animate index {idx}, there are {captures} captures");
+                }
+                return Ok(indices.clone());
+            }
+            Self::Top(k) => *k,
+        };
+
+        let motion = measure_motion(args, temp_dir, spinner)?;
+        let mut ranked: Vec<_> = (0..motion.len()).collect();
+        ranked.sort_by(|a, b| motion[*b].total_cmp(&motion[*a]));
+        ranked.truncate(k);
+        Ok(ranked)
+    }
+}
+
+impl FromStr for Animate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(k) = s.trim().strip_prefix("top:") {
+            let k = k.parse().context("invalid top:K")?;
+            return Ok(Self::Top(k));
+        }
+        let indices = s
+            .split(',')
+            .map(|idx| idx.trim().parse().context("invalid capture index"))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self::Indices(indices))
+    }
+}
+
+/// Capture to render larger in the grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hero {
+    First,
+    Scene,
+    At(HumanDuration),
+}
+
+impl Hero {
+    /// Return the index of the hero capture.
+    fn select(self, templates: &[OutTemplate], capture_dir: &Path) -> anyhow::Result<usize> {
+        match self {
+            Self::First => Ok(0),
+            Self::At(at) => Ok(templates
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    let a = (a.seconds as f32 - at.seconds).abs();
+                    let b = (b.seconds as f32 - at.seconds).abs();
+                    a.total_cmp(&b)
+                })
+                .map_or(0, |(idx, _)| idx)),
+            Self::Scene => {
+                let images = templates
+                    .par_iter()
+                    .map(|tmpl| metric::load_luma(&capture_dir.join(tmpl.with_frame(1))))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let score = |idx: usize| {
+                    let neighbours = [idx.checked_sub(1), Some(idx + 1)];
+                    let diffs: Vec<_> = neighbours
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|n| images.get(n))
+                        .map(|n| metric::difference(&images[idx], n))
+                        .collect();
+                    diffs.iter().sum::<f32>() / diffs.len().max(1) as f32
+                };
+                Ok((0..images.len())
+                    .max_by(|a, b| score(*a).total_cmp(&score(*b)))
+                    .unwrap_or(0))
+            }
+        }
+    }
+}
+
+impl FromStr for Hero {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "first" => Ok(Self::First),
+            "scene" => Ok(Self::Scene),
+            time => Ok(Self::At(time.parse()?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runs(order: &[u32], values: &[u8], tolerance: u8, chunk_len: usize) -> Vec<(u32, usize)> {
+        frame_runs(
+            order,
+            chunk_len,
+            |f| Ok(values[f as usize]),
+            |a, b| a.abs_diff(*b) <= tolerance,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn frame_runs_identical() {
+        let values = [5, 5, 5, 9, 9, 5];
+        let order: Vec<_> = (0..6).collect();
+        for chunk_len in 1..=6 {
+            assert_eq!(
+                runs(&order, &values, 0, chunk_len),
+                [(0, 3), (3, 2), (5, 1)],
+                "chunk_len {chunk_len}"
+            );
+        }
+    }
+
+    #[test]
+    fn frame_runs_compare_run_start() {
+        // gradual change doesn't merge everything
+        let values = [0, 2, 4, 6, 8];
+        let order: Vec<_> = (0..5).collect();
+        for chunk_len in 1..=5 {
+            assert_eq!(
+                runs(&order, &values, 2, chunk_len),
+                [(0, 2), (2, 2), (4, 1)],
+                "chunk_len {chunk_len}"
+            );
+        }
+
+        // chunks continuing a run are regrouped against its start
+        let values = [0, 0, 2, 4];
+        let order: Vec<_> = (0..4).collect();
+        for chunk_len in 1..=4 {
+            assert_eq!(
+                runs(&order, &values, 2, chunk_len),
+                [(0, 3), (3, 1)],
+                "chunk_len {chunk_len}"
+            );
+        }
+
+        let values = [0, 1, 3, 4, 5, 6, 7, 9];
+        let order: Vec<_> = (0..8).collect();
+        for chunk_len in 1..=8 {
+            assert_eq!(
+                runs(&order, &values, 2, chunk_len),
+                [(0, 2), (2, 3), (5, 2), (7, 1)],
+                "chunk_len {chunk_len}"
+            );
+        }
+    }
+
+    #[test]
+    fn frame_runs_repeated_frames() {
+        // --hold & --ping-pong repeat joined frames
+        let values = [1, 7, 7];
+        let order = [0, 0, 0, 1, 2, 1];
+        for chunk_len in 1..=6 {
+            assert_eq!(runs(&order, &values, 0, chunk_len), [(0, 3), (1, 3)]);
+        }
+    }
+
+    #[test]
+    fn hero_from_str() {
+        assert_eq!("first".parse::<Hero>().unwrap(), Hero::First);
+        assert_eq!(" scene ".parse::<Hero>().unwrap(), Hero::Scene);
+        assert_eq!(
+            "1m 30s".parse::<Hero>().unwrap(),
+            Hero::At(HumanDuration { seconds: 90.0 })
+        );
+        assert!("middle".parse::<Hero>().is_err());
+    }
+
+    #[test]
+    fn animate_from_str() {
+        assert_eq!(
+            "0, 3,7".parse::<Animate>().unwrap(),
+            Animate::Indices(vec![0, 3, 7])
+        );
+        assert_eq!("top:4".parse::<Animate>().unwrap(), Animate::Top(4));
+        assert!("top:x".parse::<Animate>().is_err());
+        assert!("1,-2".parse::<Animate>().is_err());
+        assert!("".parse::<Animate>().is_err());
+    }
+
+    #[test]
+    fn html_rejects_unsupported_options() {
+        use clap::Parser;
+
+        for flag in [
+            &["--timeline"][..],
+            &["--hero", "first"],
+            &["--max-size", "2MB"],
+        ] {
+            let args = [
+                &["vcs", "-c", "2", "-H", "100", "-n", "4", "-o", "sheet.html"],
+                flag,
+                &["v.mkv"],
+            ];
+            let vcs = Vcs::try_parse_from(args.concat()).unwrap();
+            let err = vcs.run().unwrap_err().to_string();
+            assert_eq!(err, format!("{} is not supported for html output", flag[0]));
+        }
+    }
+
+    fn playback(args: &[&str], frames: u32) -> Vec<u32> {
+        use clap::Parser;
+
+        let args = [
+            &["vcs", "-c", "2", "-H", "100", "-n", "4"],
+            args,
+            &["v.mkv"],
+        ]
+        .concat();
+        Vcs::try_parse_from(args)
+            .unwrap()
+            .playback((0..frames).collect())
+    }
+
+    #[test]
+    fn playback_ping_pong() {
+        assert_eq!(playback(&["--ping-pong"], 4), [0, 1, 2, 3, 2, 1]);
+        // nothing to reverse between the first & last frames
+        assert_eq!(playback(&["--ping-pong"], 2), [0, 1]);
+        assert_eq!(playback(&["--ping-pong"], 1), [0]);
+        assert_eq!(playback(&[], 3), [0, 1, 2]);
+    }
+
+    #[test]
+    fn playback_hold() {
+        // 100ms at the default 20fps
+        assert_eq!(playback(&["--hold", "100ms"], 3), [0, 0, 0, 1, 2]);
+        // the held first frame is not part of the ping-pong reverse
+        assert_eq!(
+            playback(&["--hold", "100ms", "--ping-pong", "--avif-fps", "10"], 3),
+            [0, 0, 1, 2, 1]
+        );
     }
 }
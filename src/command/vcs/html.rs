@@ -0,0 +1,235 @@
+//! Interactive html contact sheet.
+use crate::command::{ExtractData, label};
+use anyhow::Context;
+use rayon::prelude::*;
+use std::{fmt::Write, fs, io::Cursor, path::Path};
+
+/// Html page options.
+pub struct Page<'a> {
+    pub video: &'a Path,
+    pub columns: u32,
+    /// Hover preview playback framerate.
+    pub fps: f32,
+    /// Frames of a fully animated capture, captures with fewer frames play slower.
+    pub capture_frames: u32,
+    pub jpeg_quality: u8,
+    /// Embed frames as data uris for a self-contained page, otherwise write
+    /// frames into a "NAME_files" directory next to the page.
+    pub embed: bool,
+}
+
+impl Page<'_> {
+    /// Write a html page of all capture frames of `extract` read from `capture_dir`.
+    pub fn write(
+        &self,
+        extract: &ExtractData,
+        capture_dir: &Path,
+        out: &Path,
+    ) -> anyhow::Result<()> {
+        let Self {
+            video,
+            columns,
+            fps,
+            capture_frames,
+            jpeg_quality,
+            embed,
+        } = self;
+
+        let files_name = format!(
+            "{}_files",
+            out.file_stem().unwrap_or_default().to_string_lossy()
+        );
+        let files_dir = out.with_file_name(&files_name);
+        if !embed {
+            fs::create_dir_all(&files_dir)?;
+        }
+
+        // encode every labelled capture frame as a jpeg, returning its url
+        let frames: Vec<Vec<String>> = extract
+            .out_templates
+            .par_iter()
+            .map(|tmpl| {
+                let label = label::seconds_text(tmpl.seconds);
+                (1..=tmpl.frames)
+                    .map(|f| {
+                        let path = capture_dir.join(tmpl.with_frame(f));
+                        let img = image::ImageReader::open(&path)?
+                            .decode()
+                            .with_context(|| format!("{path:?}"))?;
+                        let img = label::draw(img, &label, &label::Config::default())?;
+                        let mut jpeg = Vec::new();
+                        image::codecs::jpeg::JpegEncoder::new_with_quality(
+                            Cursor::new(&mut jpeg),
+                            *jpeg_quality,
+                        )
+                        .encode_image(&img.into_rgb8())?;
+
+                        if *embed {
+                            return Ok(format!("data:image/jpeg;base64,{}", base64(&jpeg)));
+                        }
+                        let file = Path::new(&tmpl.with_frame(f)).with_extension("jpg");
+                        fs::write(files_dir.join(&file), jpeg)?;
+                        Ok(url_encode(&format!("{files_name}/{}", file.display())))
+                    })
+                    .collect()
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let name = video.file_name().unwrap_or_default().to_string_lossy();
+        let video_url = file_url(video)?;
+
+        let mut html = String::new();
+        writeln!(
+            html,
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ background: #111; color: #eee; font-family: sans-serif; margin: 1em; }}
+header {{ margin-bottom: 1em; }}
+header h1 {{ font-size: 1.3em; margin: 0; }}
+header p {{ margin: 0.3em 0; color: #aaa; }}
+.grid {{ display: grid; grid-template-columns: repeat({columns}, 1fr); gap: 2px; }}
+.grid img {{ width: 100%; display: block; }}
+</style>
+</head>
+<body>
+<header>
+<h1>{title}</h1>
+<p>Duration {duration} &middot; {captures} captures</p>
+</header>
+<div class="grid">"#,
+            title = html_escape(&name),
+            duration = label::seconds_text(extract.video_duration_s as _),
+            captures = extract.out_templates.len(),
+        )?;
+
+        for (idx, (tmpl, frames)) in extract.out_templates.iter().zip(&frames).enumerate() {
+            writeln!(
+                html,
+                r#"<a href="{video_url}#t={seconds}" title="{label}"><img data-idx="{idx}" data-ms="{ms}" src="{src}"></a>"#,
+                seconds = tmpl.seconds,
+                ms = (1000.0 * *capture_frames as f32 / (fps * tmpl.frames.max(1) as f32)).round(),
+                label = label::seconds_text(tmpl.seconds),
+                src = frames[0],
+            )?;
+        }
+
+        writeln!(html, "</div>\n<script>\nconst FRAMES = [")?;
+        for frames in &frames {
+            writeln!(html, "{},", serde_json::to_string(frames)?)?;
+        }
+        writeln!(
+            html,
+            r#"];
+document.querySelectorAll(".grid img").forEach(img => {{
+  const frames = FRAMES[img.dataset.idx];
+  if (frames.length < 2) return;
+  let timer = null;
+  img.addEventListener("mouseenter", () => {{
+    let f = 0;
+    timer = setInterval(() => {{
+      f = (f + 1) % frames.length;
+      img.src = frames[f];
+    }}, img.dataset.ms);
+  }});
+  img.addEventListener("mouseleave", () => {{
+    clearInterval(timer);
+    img.src = frames[0];
+  }});
+}});
+</script>
+</body>
+</html>"#
+        )?;
+
+        fs::write(out, html).with_context(|| format!("failed to write {out:?}"))
+    }
+}
+
+/// Absolute `file://` url for a local path.
+fn file_url(path: &Path) -> anyhow::Result<String> {
+    let path = fs::canonicalize(path)?;
+    let path = path.to_string_lossy();
+    // windows verbatim paths, e.g. \\?\C:\video.mkv
+    let path = path
+        .strip_prefix(r"\\?\")
+        .unwrap_or(&path)
+        .replace('\\', "/");
+
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        url.push('/');
+    }
+    url += &url_encode(&path);
+    Ok(url)
+}
+
+/// Percent encode a url path.
+fn url_encode(path: &str) -> String {
+    let mut url = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                url.push(b as char)
+            }
+            _ => _ = write!(url, "%{b:02X}"),
+        }
+    }
+    url
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Standard padded base64 encoding.
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = match chunk {
+            [a, b, c] => u32::from_be_bytes([0, *a, *b, *c]),
+            [a, b] => u32::from_be_bytes([0, *a, *b, 0]),
+            [a] => u32::from_be_bytes([0, *a, 0, 0]),
+            _ => unreachable!(),
+        };
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(TABLE[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(&[0xff, 0xfe, 0xfd]), "//79");
+    }
+
+    #[test]
+    fn url_encode_frame_path() {
+        assert_eq!(
+            url_encode("my sheet_files/a&b-05s-1.jpg"),
+            "my%20sheet_files/a%26b-05s-1.jpg"
+        );
+    }
+}
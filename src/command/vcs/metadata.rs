@@ -0,0 +1,139 @@
+//! Sidecar json describing how a sheet was generated.
+use crate::command::{Cell, JoinData, OutTemplate, label};
+use anyhow::Context;
+use std::{
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Bytes hashed from both the start & end of the source video.
+const HASH_SAMPLE_LEN: u64 = 1024 * 1024;
+
+#[derive(Debug, serde::Serialize)]
+pub struct Metadata<'a> {
+    pub vimg_version: &'static str,
+    pub source: Source<'a>,
+    /// Frames per capture.
+    pub capture_frames: u32,
+    /// Duration of each capture in seconds.
+    pub capture_time: f32,
+    pub grid: Grid,
+    pub captures: Vec<Capture>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Source<'a> {
+    pub path: &'a Path,
+    pub size: u64,
+    /// Duration in seconds.
+    pub duration: f32,
+    /// "fnv1a64:HEX" of the file size, first & last MiB of the file.
+    pub hash: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Grid {
+    pub width: u32,
+    pub height: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Capture {
+    /// Capture start time in seconds.
+    pub seconds: u32,
+    pub label: String,
+    /// Distinct frames captured, 1 for static captures.
+    pub frames: u32,
+    #[serde(flatten)]
+    pub cell: Cell,
+}
+
+impl<'a> Metadata<'a> {
+    pub fn new(
+        video: &'a Path,
+        capture_time: f32,
+        capture_frames: u32,
+        video_duration_s: f32,
+        templates: &[OutTemplate],
+        join: &JoinData,
+    ) -> anyhow::Result<Self> {
+        let captures = templates
+            .iter()
+            .zip(&join.cells)
+            .map(|(tmpl, cell)| Capture {
+                seconds: tmpl.seconds,
+                label: label::seconds_text(tmpl.seconds),
+                frames: tmpl.frames,
+                cell: *cell,
+            })
+            .collect();
+
+        Ok(Self {
+            vimg_version: env!("CARGO_PKG_VERSION"),
+            source: Source {
+                path: video,
+                size: fs::metadata(video)?.len(),
+                duration: video_duration_s,
+                hash: sample_hash(video)?,
+            },
+            capture_frames,
+            capture_time,
+            grid: Grid {
+                width: join.width,
+                height: join.height,
+                columns: join.columns,
+                rows: join.rows,
+            },
+            captures,
+        })
+    }
+
+    /// Write as pretty json to `path`.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n").with_context(|| format!("failed to write {path:?}"))
+    }
+}
+
+/// Fast fnv-1a hash of the file size & start/end bytes of a, potentially large, file.
+fn sample_hash(path: &Path) -> anyhow::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut hash = Fnv1a64::default();
+    hash.write(&len.to_le_bytes());
+
+    let mut buf = Vec::new();
+    (&mut file).take(HASH_SAMPLE_LEN).read_to_end(&mut buf)?;
+    hash.write(&buf);
+
+    if len > HASH_SAMPLE_LEN {
+        buf.clear();
+        file.seek(SeekFrom::Start(HASH_SAMPLE_LEN.max(len - HASH_SAMPLE_LEN)))?;
+        file.read_to_end(&mut buf)?;
+        hash.write(&buf);
+    }
+
+    Ok(format!("fnv1a64:{:016x}", hash.0))
+}
+
+/// Fnv-1a 64 bit hasher, stable across platforms & rust versions.
+struct Fnv1a64(u64);
+
+impl Default for Fnv1a64 {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Fnv1a64 {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Video timeline bar drawn across the bottom of the sheet.
+
+const BAR: image::Rgb<u8> = image::Rgb([70, 70, 70]);
+const IGNORED: image::Rgb<u8> = image::Rgb([30, 30, 30]);
+const TICK: image::Rgb<u8> = image::Rgb([230, 230, 230]);
+const PLAYHEAD: image::Rgb<u8> = image::Rgb([230, 50, 50]);
+
+/// Full video duration with the captured points.
+pub struct Timeline {
+    pub duration_s: f32,
+    /// Seconds ignored at the start & end when calculating capture points.
+    pub ignore_start_s: f32,
+    pub ignore_end_s: f32,
+    /// Start seconds of each capture & whether it is animated.
+    pub captures: Vec<(f32, bool)>,
+    /// Duration of each capture in seconds.
+    pub capture_time_s: f32,
+}
+
+impl Timeline {
+    /// Render the bar with a tick for each capture.
+    ///
+    /// `progress`, if set, draws a playhead for each capture at the given
+    /// fraction `0..=1` through the capture time. Playheads of static
+    /// captures stay at their start.
+    pub fn render(&self, width: u32, height: u32, progress: Option<f32>) -> image::RgbImage {
+        let mut img = image::RgbImage::from_pixel(width, height, BAR);
+        let x_at = |s: f32| {
+            ((s / self.duration_s.max(f32::EPSILON) * width as f32) as u32)
+                .min(width.saturating_sub(1))
+        };
+
+        let ignore_end_x = x_at(self.duration_s - self.ignore_end_s);
+        for x in (0..x_at(self.ignore_start_s)).chain(ignore_end_x..width) {
+            for y in 0..height {
+                img.put_pixel(x, y, IGNORED);
+            }
+        }
+
+        // short ticks from the top, playheads full height
+        for &(start, animated) in &self.captures {
+            for y in 0..height.div_ceil(2) {
+                img.put_pixel(x_at(start), y, TICK);
+            }
+            if let Some(progress) = progress {
+                let progress = if animated { progress } else { 0.0 };
+                let x = x_at(start + self.capture_time_s * progress);
+                for y in 0..height {
+                    img.put_pixel(x, y, PLAYHEAD);
+                }
+            }
+        }
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playheads(timeline: &Timeline, progress: f32) -> Vec<u32> {
+        let img = timeline.render(100, 8, Some(progress));
+        // playheads are the only marks reaching the bottom row
+        (0..100)
+            .filter(|x| *img.get_pixel(*x, 7) == PLAYHEAD)
+            .collect()
+    }
+
+    #[test]
+    fn static_capture_playhead_fixed() {
+        let timeline = Timeline {
+            duration_s: 100.0,
+            ignore_start_s: 0.0,
+            ignore_end_s: 0.0,
+            captures: vec![(10.0, false), (50.0, true)],
+            capture_time_s: 10.0,
+        };
+        assert_eq!(playheads(&timeline, 0.0), [10, 50]);
+        assert_eq!(playheads(&timeline, 1.0), [10, 60]);
+    }
+}
@@ -0,0 +1,131 @@
+//! Audio loudness strip drawn beneath the grid.
+use crate::process::{self, CommandExt};
+use anyhow::{Context, ensure};
+use std::{
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Mono sample rate audio is decoded at, plenty for loudness.
+const SAMPLE_RATE: u32 = 4000;
+/// Loudness below this is drawn as silence.
+const FLOOR_DB: f32 = -60.0;
+
+const BACKGROUND: image::Rgb<u8> = image::Rgb([0, 0, 0]);
+const WAVE: image::Rgb<u8> = image::Rgb([150, 150, 150]);
+const MARKER: image::Rgb<u8> = image::Rgb([255, 190, 40]);
+
+/// Decode the audio of `video` & return rms loudness of `buckets` equal
+/// time slices in the range `0..=1`.
+pub fn loudness(video: &Path, buckets: u32, duration_s: f32) -> anyhow::Result<Vec<f32>> {
+    ensure!(buckets > 0, "invalid waveform width");
+    let mut ffmpeg = Command::new("ffmpeg")
+        .arg2("-v", "error")
+        .arg2("-i", video)
+        .arg("-vn")
+        .arg2("-ac", 1)
+        .arg2("-ar", SAMPLE_RATE)
+        .arg2("-f", "s16le")
+        .arg("-")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run ffmpeg, is it installed & in $PATH?")?;
+
+    let mut levels = Levels {
+        samples_per_bucket: (f64::from(duration_s) * f64::from(SAMPLE_RATE) / f64::from(buckets))
+            .max(1.0),
+        sum_sq: vec![0.0; buckets as usize],
+        counts: vec![0; buckets as usize],
+        samples: 0,
+    };
+
+    // drain stderr concurrently so lots of decode errors can't block ffmpeg
+    let stderr = process::read_to_end(ffmpeg.stderr.take());
+    let mut stdout = ffmpeg.stdout.take().context("missing ffmpeg stdout")?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut leftover = None;
+    loop {
+        let len = stdout.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        let mut bytes = &buf[..len];
+        // a sample may be split across reads
+        if let Some(lo) = leftover.take() {
+            levels.add(i16::from_le_bytes([lo, bytes[0]]));
+            bytes = &bytes[1..];
+        }
+        let mut chunks = bytes.chunks_exact(2);
+        for s in &mut chunks {
+            levels.add(i16::from_le_bytes([s[0], s[1]]));
+        }
+        leftover = chunks.remainder().first().copied();
+    }
+
+    let status = ffmpeg.wait()?;
+    let stderr = stderr.join().unwrap_or_default();
+    ensure!(
+        status.success() && levels.samples > 0,
+        "ffmpeg audio decode failed, does the video have audio?\n---stderr---\n{}\n------",
+        String::from_utf8_lossy(&stderr).trim(),
+    );
+
+    Ok(levels.loudness())
+}
+
+/// Per-bucket sample accumulator.
+struct Levels {
+    samples_per_bucket: f64,
+    sum_sq: Vec<f64>,
+    counts: Vec<u32>,
+    samples: u64,
+}
+
+impl Levels {
+    fn add(&mut self, sample: i16) {
+        let bucket =
+            ((self.samples as f64 / self.samples_per_bucket) as usize).min(self.sum_sq.len() - 1);
+        self.sum_sq[bucket] += f64::from(sample).powi(2);
+        self.counts[bucket] += 1;
+        self.samples += 1;
+    }
+
+    /// Rms loudness of each bucket mapped from `FLOOR_DB..=0` dB to `0..=1`.
+    fn loudness(self) -> Vec<f32> {
+        self.sum_sq
+            .into_iter()
+            .zip(self.counts)
+            .map(|(sum_sq, count)| {
+                let rms = (sum_sq / f64::from(count.max(1))).sqrt() as f32 / f32::from(i16::MAX);
+                let db = 20.0 * rms.max(f32::EPSILON).log10();
+                (1.0 - db / FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// Render a strip, one pixel column per `loudness` value, mirrored around the
+/// centre with vertical lines at each `markers` position in the range `0..=1`.
+pub fn render(loudness: &[f32], height: u32, markers: &[f32]) -> image::RgbImage {
+    let width = loudness.len() as u32;
+    let mut img = image::RgbImage::from_pixel(width, height, BACKGROUND);
+    let mid = height as f32 / 2.0;
+
+    for (x, level) in loudness.iter().enumerate() {
+        let half = (level * mid).max(0.5);
+        let (top, bottom) = ((mid - half).floor() as u32, (mid + half).ceil() as u32);
+        for y in top..bottom.min(height) {
+            img.put_pixel(x as _, y, WAVE);
+        }
+    }
+
+    for marker in markers {
+        let x = ((marker * width as f32) as u32).min(width.saturating_sub(1));
+        for y in 0..height {
+            img.put_pixel(x, y, MARKER);
+        }
+    }
+    img
+}
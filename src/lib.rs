@@ -0,0 +1,18 @@
+//! Video images: contact sheets, thumbnails, sprites & previews generated with ffmpeg.
+pub mod command;
+mod process;
+pub mod temporary;
+
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(version, about)]
+pub enum Command {
+    Vcs(command::Vcs),
+    Join(command::Join),
+    Extract(command::Extract),
+    Sprites(command::Sprites),
+    Thumb(command::Thumb),
+    Preview(command::Preview),
+    PrintCompletions(command::PrintCompletions),
+}
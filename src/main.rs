@@ -1,25 +1,13 @@
-mod command;
-mod process;
-mod temporary;
+// dependencies are used by the lib
+#![allow(unused_crate_dependencies)]
 
 use clap::Parser;
-
-#[derive(Parser)]
-#[command(version, about)]
-enum Command {
-    Vcs(command::Vcs),
-    Join(command::Join),
-    Extract(command::Extract),
-    PrintCompletions(command::PrintCompletions),
-}
+use vimg::{Command, temporary};
 
 fn main() -> anyhow::Result<()> {
     let cmd = Command::parse();
 
-    _ = ctrlc::set_handler(|| {
-        temporary::clean();
-        std::process::exit(1);
-    });
+    temporary::clean_on_interrupt();
 
     let result = run(cmd);
 
@@ -31,13 +19,18 @@ fn main() -> anyhow::Result<()> {
 fn run(cmd: Command) -> anyhow::Result<()> {
     match cmd {
         Command::Vcs(c) => c.run()?,
-        Command::Join(c) => c.run()?,
+        Command::Join(c) => {
+            c.run()?;
+        }
         Command::Extract(c) => {
             let ex = c.run()?;
             for msg in ex.warnings {
                 eprintln!("Warning: {msg}");
             }
         }
+        Command::Sprites(c) => c.run()?,
+        Command::Thumb(c) => c.run()?,
+        Command::Preview(c) => c.run()?,
         Command::PrintCompletions(c) => c.run(),
     }
 
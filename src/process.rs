@@ -1,4 +1,13 @@
-use std::{borrow::Cow, ffi::OsStr, sync::Arc};
+use anyhow::bail;
+use std::{
+    borrow::Cow,
+    ffi::OsStr,
+    io::Read,
+    process::{Output, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
 #[allow(dead_code)]
 pub trait CommandExt {
@@ -13,6 +22,9 @@ pub trait CommandExt {
 
     /// Adds two arguments if `condition` otherwise noop.
     fn arg2_if(&mut self, condition: bool, a: impl ArgString, b: impl ArgString) -> &mut Self;
+
+    /// Like `output` but kills the process & errors if it runs longer than `timeout`.
+    fn output_timeout(&mut self, timeout: Duration) -> anyhow::Result<Output>;
 }
 
 impl CommandExt for std::process::Command {
@@ -40,6 +52,47 @@ impl CommandExt for std::process::Command {
             false => self,
         }
     }
+
+    fn output_timeout(&mut self, timeout: Duration) -> anyhow::Result<Output> {
+        let mut child = self
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // read concurrently so a full pipe can't block the process
+        let stdout = read_to_end(child.stdout.take());
+        let stderr = read_to_end(child.stderr.take());
+
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if start.elapsed() > timeout {
+                _ = child.kill();
+                _ = child.wait();
+                bail!("timed out after {}s", timeout.as_secs_f32());
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+/// Read `pipe` to the end on a new thread, so a full pipe can't block the process.
+pub fn read_to_end(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
 }
 
 pub trait ArgString {
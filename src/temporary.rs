@@ -13,6 +13,14 @@ pub fn clean() {
     }
 }
 
+/// [`clean`] & exit when the process is interrupted, e.g. by ctrl-c.
+pub fn clean_on_interrupt() {
+    _ = ctrlc::set_handler(|| {
+        clean();
+        std::process::exit(1);
+    });
+}
+
 /// Return a temporary directory that is distinct per process/run.
 ///
 /// Configured --temp-dir is used as a parent or, if not set, the current working dir.